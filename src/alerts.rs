@@ -0,0 +1,613 @@
+use crate::config::{AlertCondition, AlertRule};
+use crate::model::{GroupInfo, TopicInfo};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use hyper_tls::HttpsConnector;
+use log::warn;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::time::Duration;
+
+/// Notifications kept for a webhook which can't be reached, the oldest ones being dropped first.
+const MAX_UNDELIVERED: usize = 1000;
+
+/// How long a post waits for the webhook to answer, the refresh of the exporter waiting as long.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// State of the cluster the rules are evaluated against, as fetched by a successful refresh.
+pub struct ClusterState<'a> {
+    pub groups: &'a [GroupInfo],
+    pub topics: &'a [TopicInfo],
+    /// Lag of each (group, topic), None until the committed offsets are loaded.
+    pub lags: Option<&'a HashMap<(String, String), i64>>,
+}
+
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    /// What the alert is about: a group, a group on a topic, or a topic.
+    pub subject: String,
+    pub message: String,
+    /// When the condition started to hold, in milliseconds since the epoch.
+    pub since_ms: i64,
+}
+
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertStatus {
+    Firing,
+    Resolved,
+}
+
+/// A change of an alert, posted to the webhook.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub status: AlertStatus,
+    #[serde(flatten)]
+    pub alert: Alert,
+}
+
+/// The rules, and the alerts they raised so far. An alert is identified by its rule and subject.
+#[derive(Default)]
+pub struct Alerts {
+    rules: Vec<AlertRule>,
+    /// When the conditions not firing yet started to hold.
+    pending: HashMap<(String, String), i64>,
+    firing: BTreeMap<(String, String), Alert>,
+}
+
+impl Alerts {
+    pub fn new(rules: Vec<AlertRule>) -> Alerts {
+        Alerts {
+            rules,
+            ..Alerts::default()
+        }
+    }
+
+    /// Firing alerts, ordered by rule and subject.
+    pub fn firing(&self) -> Vec<&Alert> {
+        self.firing.values().collect()
+    }
+
+    /// Evaluate the rules against the state of the cluster, returning the alerts which started
+    /// or stopped firing. A condition fires once it has held for the `for_ms` of its rule. The
+    /// rules whose data is unknown keep their alerts as they are, rather than resolving them
+    /// until the data is back.
+    pub fn evaluate(&mut self, state: &ClusterState, now_ms: i64) -> Vec<Notification> {
+        let mut matching: HashMap<(String, String), String> = HashMap::new();
+        let mut unknown: HashSet<&str> = HashSet::new();
+        for rule in &self.rules {
+            match matches(&rule.condition, state) {
+                Some(subjects) => {
+                    for (subject, message) in subjects {
+                        matching.insert((rule.name.to_owned(), subject), message);
+                    }
+                }
+                None => {
+                    unknown.insert(&rule.name);
+                }
+            }
+        }
+        let known = |(rule, _): &(String, String)| !unknown.contains(rule.as_str());
+
+        let mut notifications = vec![];
+        self.pending
+            .retain(|key, _| !known(key) || matching.contains_key(key));
+        let resolved: Vec<(String, String)> = self
+            .firing
+            .keys()
+            .filter(|key| known(key) && !matching.contains_key(*key))
+            .cloned()
+            .collect();
+        for key in resolved {
+            let alert = self.firing.remove(&key).unwrap();
+            notifications.push(Notification {
+                status: AlertStatus::Resolved,
+                alert,
+            });
+        }
+        for rule in &self.rules {
+            let mut keys: Vec<&(String, String)> = matching
+                .keys()
+                .filter(|(name, _)| *name == rule.name)
+                .collect();
+            keys.sort();
+            for key in keys {
+                let message = matching[key].to_owned();
+                if let Some(alert) = self.firing.get_mut(key) {
+                    alert.message = message;
+                    continue;
+                }
+                let since_ms = *self.pending.entry(key.clone()).or_insert(now_ms);
+                if now_ms - since_ms >= rule.for_ms as i64 {
+                    self.pending.remove(key);
+                    let alert = Alert {
+                        rule: key.0.to_owned(),
+                        subject: key.1.to_owned(),
+                        message,
+                        since_ms,
+                    };
+                    self.firing.insert(key.clone(), alert.clone());
+                    notifications.push(Notification {
+                        status: AlertStatus::Firing,
+                        alert,
+                    });
+                }
+            }
+        }
+        notifications
+    }
+}
+
+/// Subjects matching a condition, with a description of their state, None when the state the
+/// condition depends on is unknown.
+fn matches(condition: &AlertCondition, state: &ClusterState) -> Option<Vec<(String, String)>> {
+    Some(match condition {
+        AlertCondition::GroupLag {
+            group,
+            topic: Some(topic),
+            max_lag,
+        } => state
+            .lags?
+            .get(&(group.to_owned(), topic.to_owned()))
+            .filter(|&lag| lag > max_lag)
+            .map(|lag| {
+                vec![(
+                    format!("{} on {}", group, topic),
+                    format!("lag {} > {}", lag, max_lag),
+                )]
+            })
+            .unwrap_or_default(),
+        AlertCondition::GroupLag {
+            group,
+            topic: None,
+            max_lag,
+        } => {
+            let lags: Vec<i64> = state
+                .lags?
+                .iter()
+                .filter(|((g, _), _)| g == group)
+                .map(|(_, &lag)| lag)
+                .collect();
+            let lag: i64 = lags.iter().sum();
+            if !lags.is_empty() && lag > *max_lag {
+                vec![(group.to_owned(), format!("lag {} > {}", lag, max_lag))]
+            } else {
+                vec![]
+            }
+        }
+        AlertCondition::UnderReplicatedPartitions { topic } => state
+            .topics
+            .iter()
+            .filter(|t| t.under_replicated > 0)
+            .filter(|t| topic.as_ref().is_none_or(|topic| &t.name == topic))
+            .map(|t| {
+                (
+                    t.name.to_owned(),
+                    format!("{} under-replicated partitions", t.under_replicated),
+                )
+            })
+            .collect(),
+        AlertCondition::GroupState { group, states } => state
+            .groups
+            .iter()
+            .filter(|g| group.as_ref().is_none_or(|group| &g.name == group))
+            .filter(|g| states.contains(&g.state))
+            .map(|g| (g.name.to_owned(), format!("state {}", g.state)))
+            .collect(),
+    })
+}
+
+/// Lag of each (group, topic) over the partitions whose high watermark is known.
+pub fn topic_lags(
+    committed: &[(String, String, i32, i64)],
+    high_watermarks: &HashMap<(String, i32), i64>,
+) -> HashMap<(String, String), i64> {
+    let mut lags = HashMap::new();
+    for (group, topic, partition, offset) in committed {
+        if let Some(high) = high_watermarks.get(&(topic.to_owned(), *partition)) {
+            *lags
+                .entry((group.to_owned(), topic.to_owned()))
+                .or_insert(0) += (high - offset).max(0);
+        }
+    }
+    lags
+}
+
+#[derive(Serialize)]
+struct WebhookPayload<'a> {
+    alerts: &'a [Notification],
+}
+
+/// Posts the notifications to the webhook of the config, over HTTP or HTTPS.
+pub struct Webhook {
+    url: String,
+    client: Client<HttpsConnector<HttpConnector>>,
+    timeout: Duration,
+    /// Notifications whose post failed, sent again with the next ones.
+    undelivered: Vec<Notification>,
+}
+
+impl Webhook {
+    pub fn new(url: &str) -> Webhook {
+        Webhook {
+            url: url.to_owned(),
+            client: Client::builder().build(HttpsConnector::new()),
+            timeout: WEBHOOK_TIMEOUT,
+            undelivered: vec![],
+        }
+    }
+
+    /// Post the notifications after the ones which could not be delivered before, keeping them
+    /// all for the next call when the post fails.
+    pub async fn send(&mut self, notifications: Vec<Notification>) -> Result<(), String> {
+        self.undelivered.extend(notifications);
+        if self.undelivered.is_empty() {
+            return Ok(());
+        }
+        let dropped = self.undelivered.len().saturating_sub(MAX_UNDELIVERED);
+        if dropped > 0 {
+            warn!("Dropping {} undelivered alert notifications", dropped);
+            self.undelivered.drain(..dropped);
+        }
+        self.post(&self.undelivered).await?;
+        self.undelivered.clear();
+        Ok(())
+    }
+
+    pub async fn post(&self, notifications: &[Notification]) -> Result<(), String> {
+        let body = serde_json::to_vec(&WebhookPayload {
+            alerts: notifications,
+        })
+        .map_err(|e| e.to_string())?;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&self.url)
+            .header("Content-Type", "application/json")
+            .body(Body::from(body))
+            .map_err(|e| e.to_string())?;
+        let response = tokio::time::timeout(self.timeout, self.client.request(request))
+            .await
+            .map_err(|_| format!("{} did not answer within {:?}", self.url, self.timeout))?
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("{} answered {}", self.url, response.status()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cluster::ClusterClient;
+    use crate::fake_cluster::FakeCluster;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Response, Server};
+    use std::convert::Infallible;
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    const MINUTE: i64 = 60 * 1000;
+
+    fn rule(name: &str, condition: AlertCondition, for_ms: u64) -> AlertRule {
+        AlertRule {
+            name: name.to_owned(),
+            condition,
+            for_ms,
+        }
+    }
+
+    fn lag_rule() -> AlertRule {
+        rule(
+            "billing-lag",
+            AlertCondition::GroupLag {
+                group: String::from("billing"),
+                topic: None,
+                max_lag: 100,
+            },
+            5 * MINUTE as u64,
+        )
+    }
+
+    fn evaluate(
+        alerts: &mut Alerts,
+        cluster: &FakeCluster,
+        lags: &[(&str, &str, i64)],
+        now_ms: i64,
+    ) -> Vec<Notification> {
+        let groups = cluster.get_group_infos().unwrap();
+        let topics = cluster.get_topic_infos(&groups).unwrap();
+        let lags = lags
+            .iter()
+            .map(|&(group, topic, lag)| ((group.to_owned(), topic.to_owned()), lag))
+            .collect();
+        let state = ClusterState {
+            groups: &groups,
+            topics: &topics,
+            lags: Some(&lags),
+        };
+        alerts.evaluate(&state, now_ms)
+    }
+
+    fn statuses(notifications: &[Notification]) -> Vec<(AlertStatus, &str, &str)> {
+        notifications
+            .iter()
+            .map(|n| (n.status, n.alert.subject.as_str(), n.alert.message.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn fires_once_the_lag_stays_above_the_threshold() {
+        let cluster = FakeCluster::sample();
+        let mut alerts = Alerts::new(vec![lag_rule()]);
+        let lags = [("billing", "orders", 80), ("billing", "payments", 30)];
+
+        assert!(evaluate(&mut alerts, &cluster, &lags, 0).is_empty());
+        assert!(evaluate(&mut alerts, &cluster, &lags, 4 * MINUTE).is_empty());
+        let fired = evaluate(&mut alerts, &cluster, &lags, 5 * MINUTE);
+        assert_eq!(
+            statuses(&fired),
+            vec![(AlertStatus::Firing, "billing", "lag 110 > 100")]
+        );
+        assert_eq!(fired[0].alert.since_ms, 0);
+        assert_eq!(alerts.firing().len(), 1);
+        // Fired once only, with an up to date message.
+        let lags = [("billing", "orders", 180)];
+        assert!(evaluate(&mut alerts, &cluster, &lags, 6 * MINUTE).is_empty());
+        assert_eq!(alerts.firing()[0].message, "lag 180 > 100");
+
+        let resolved = evaluate(
+            &mut alerts,
+            &cluster,
+            &[("billing", "orders", 0)],
+            7 * MINUTE,
+        );
+        assert_eq!(
+            statuses(&resolved),
+            vec![(AlertStatus::Resolved, "billing", "lag 180 > 100")]
+        );
+        assert!(alerts.firing().is_empty());
+    }
+
+    #[test]
+    fn restarts_the_delay_when_the_condition_stops_holding() {
+        let cluster = FakeCluster::sample();
+        let mut alerts = Alerts::new(vec![lag_rule()]);
+        let high = [("billing", "orders", 200)];
+
+        evaluate(&mut alerts, &cluster, &high, 0);
+        evaluate(
+            &mut alerts,
+            &cluster,
+            &[("billing", "orders", 10)],
+            3 * MINUTE,
+        );
+        evaluate(&mut alerts, &cluster, &high, 4 * MINUTE);
+        assert!(evaluate(&mut alerts, &cluster, &high, 8 * MINUTE).is_empty());
+        assert_eq!(evaluate(&mut alerts, &cluster, &high, 9 * MINUTE).len(), 1);
+    }
+
+    #[test]
+    fn keeps_the_lag_alerts_while_the_lag_is_unknown() {
+        let cluster = FakeCluster::sample();
+        let mut alerts = Alerts::new(vec![
+            lag_rule(),
+            rule(
+                "stable-groups",
+                AlertCondition::GroupState {
+                    group: None,
+                    states: vec![String::from("Stable")],
+                },
+                0,
+            ),
+        ]);
+        let high = [("billing", "orders", 200)];
+        evaluate(&mut alerts, &cluster, &high, 0);
+        evaluate(&mut alerts, &cluster, &high, 5 * MINUTE);
+        assert_eq!(alerts.firing().len(), 2);
+
+        let groups = cluster.get_group_infos().unwrap();
+        let state = ClusterState {
+            groups: &groups,
+            topics: &[],
+            lags: None,
+        };
+        assert!(alerts.evaluate(&state, 6 * MINUTE).is_empty());
+        assert_eq!(alerts.firing().len(), 2);
+        let state = ClusterState {
+            groups: &[],
+            topics: &[],
+            lags: None,
+        };
+        assert_eq!(
+            statuses(&alerts.evaluate(&state, 7 * MINUTE)),
+            vec![(AlertStatus::Resolved, "billing", "state Stable")]
+        );
+        assert_eq!(alerts.firing()[0].rule, "billing-lag");
+    }
+
+    #[test]
+    fn alerts_on_under_replicated_partitions_and_group_states() {
+        let mut cluster = FakeCluster::sample();
+        cluster.partition_mut("orders", 1).isr = vec![2];
+        cluster.partition_mut("payments", 0).isr = vec![1];
+        let mut alerts = Alerts::new(vec![
+            rule(
+                "orders-replication",
+                AlertCondition::UnderReplicatedPartitions {
+                    topic: Some(String::from("orders")),
+                },
+                0,
+            ),
+            rule(
+                "stable-groups",
+                AlertCondition::GroupState {
+                    group: None,
+                    states: vec![String::from("Stable")],
+                },
+                0,
+            ),
+        ]);
+
+        let fired = evaluate(&mut alerts, &cluster, &[], 0);
+        assert_eq!(
+            statuses(&fired),
+            vec![
+                (
+                    AlertStatus::Firing,
+                    "orders",
+                    "1 under-replicated partitions"
+                ),
+                (AlertStatus::Firing, "billing", "state Stable"),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_the_rules_of_the_config() {
+        let rules: Vec<AlertRule> = serde_json::from_str(
+            r#"[
+                {"name": "lag", "type": "group_lag", "group": "billing", "max_lag": 10, "for_ms": 60000},
+                {"name": "down", "type": "group_state", "group": "billing"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(rules[0].for_ms, 60000);
+        assert_eq!(
+            rules[1].condition,
+            AlertCondition::GroupState {
+                group: Some(String::from("billing")),
+                states: vec![String::from("Empty"), String::from("Dead")],
+            }
+        );
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn posts_the_notifications_to_the_webhook() {
+        let (requests, received) = mpsc::channel();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let stub = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let requests = requests.clone();
+                    async move {
+                        let path = request.uri().path().to_owned();
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        requests.send((path, body.to_vec())).unwrap();
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(stub));
+
+        let alert = Alert {
+            rule: String::from("billing-lag"),
+            subject: String::from("billing"),
+            message: String::from("lag 110 > 100"),
+            since_ms: 1000,
+        };
+        let notification = Notification {
+            status: AlertStatus::Firing,
+            alert,
+        };
+        Webhook::new(&url).post(&[notification]).await.unwrap();
+
+        let (path, body) = received.recv().unwrap();
+        assert_eq!(path, "/hook");
+        let json: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({"alerts": [{
+                "status": "firing",
+                "rule": "billing-lag",
+                "subject": "billing",
+                "message": "lag 110 > 100",
+                "since_ms": 1000,
+            }]})
+        );
+
+        let unreachable = Webhook::new("http://127.0.0.1:1/hook");
+        assert!(unreachable.post(&[]).await.is_err());
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn sends_the_undelivered_notifications_again() {
+        let (requests, received) = mpsc::channel();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let stub = make_service_fn(move |_| {
+            let requests = requests.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let requests = requests.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        requests.send(body.to_vec()).unwrap();
+                        Ok::<_, Infallible>(Response::new(Body::empty()))
+                    }
+                }))
+            }
+        });
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(stub));
+        let notification = |status, message: &str| Notification {
+            status,
+            alert: Alert {
+                rule: String::from("billing-lag"),
+                subject: String::from("billing"),
+                message: message.to_owned(),
+                since_ms: 1000,
+            },
+        };
+
+        let mut webhook = Webhook::new("http://127.0.0.1:1/hook");
+        let fired = notification(AlertStatus::Firing, "lag 110 > 100");
+        assert!(webhook.send(vec![fired]).await.is_err());
+        assert!(webhook.send(vec![]).await.is_err());
+        assert_eq!(webhook.undelivered.len(), 1);
+
+        webhook.url = url;
+        let resolved = notification(AlertStatus::Resolved, "lag 180 > 100");
+        webhook.send(vec![resolved]).await.unwrap();
+        assert!(webhook.undelivered.is_empty());
+        let json: serde_json::Value = serde_json::from_slice(&received.recv().unwrap()).unwrap();
+        let statuses: Vec<&str> = json["alerts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|alert| alert["status"].as_str().unwrap())
+            .collect();
+        assert_eq!(statuses, vec!["firing", "resolved"]);
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn gives_up_on_a_webhook_which_does_not_answer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let stub = make_service_fn(|_| async {
+            Ok::<_, Infallible>(service_fn(|_: Request<Body>| {
+                futures::future::pending::<Result<Response<Body>, Infallible>>()
+            }))
+        });
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(stub));
+
+        let mut webhook = Webhook::new(&url);
+        webhook.timeout = Duration::from_millis(100);
+        let notification = Notification {
+            status: AlertStatus::Firing,
+            alert: Alert {
+                rule: String::from("billing-lag"),
+                subject: String::from("billing"),
+                message: String::from("lag 110 > 100"),
+                since_ms: 1000,
+            },
+        };
+        let error = webhook.send(vec![notification]).await.unwrap_err();
+        assert_eq!(error, format!("{} did not answer within 100ms", url));
+        assert_eq!(webhook.undelivered.len(), 1);
+    }
+}
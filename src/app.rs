@@ -1,6 +1,4 @@
-use crossterm::event::{
-    poll, read, DisableMouseCapture, EnableMouseCapture, Event as CEvent, KeyCode,
-};
+use crossterm::event::{poll, read, DisableMouseCapture, EnableMouseCapture, Event as CEvent};
 use crossterm::execute;
 use crossterm::terminal::{
     disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen,
@@ -13,79 +11,617 @@ use std::thread;
 use tui::backend::CrosstermBackend;
 use tui::Terminal;
 
-use crate::handlers::handle_event;
+use crate::alerts::{Alerts, ClusterState};
+use crate::handlers::{handle_key, handle_mouse};
 use crate::kafka::KafkaWrapper;
+use crate::keymap::Keymap;
 use crate::model::{
-    ClusterInfo, Event, GroupInfo, OffsetAndMetadata, OffsetValue, TopicDetail, TopicInfo,
+    ClusterHealth, ClusterInfo, Event, GroupInfo, OffsetsStore, PartitionInfo, TopicDetail,
+    TopicInfo,
 };
-use crate::offsets_consumer::OffsetsConsumer;
+use crate::offsets_source::build_offsets_source;
+use crate::rates::{LagTracker, Throughput};
+use crate::refresher::{Refresh, Refresher, Snapshot, TopicListSnapshot};
+use futures::channel::mpsc::{unbounded, UnboundedSender};
 
-use crate::app::Context::{TopicDetailPage, TopicListPage};
-use crate::config::Config;
+use crate::app::Context::{GroupDetailPage, GroupListPage, TopicDetailPage, TopicListPage};
+use crate::config::{Config, RefreshConfig};
+use crate::theme::{no_color_requested, Theme};
 use crate::ui;
+use crate::utils::{fuzzy_match, now_ms};
 use std::borrow::Borrow;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
 use std::time::{Duration, Instant};
+use tui::layout::Rect;
 use tui::widgets::TableState;
 
+#[allow(clippy::enum_variant_names)]
+#[derive(Clone, Copy, PartialEq)]
 pub enum Context {
     TopicListPage,
     TopicDetailPage,
+    GroupListPage,
+    GroupDetailPage,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TopicSort {
+    Name,
+    Partitions,
+    Messages,
+    Throughput,
+}
+
+impl TopicSort {
+    fn next(self) -> TopicSort {
+        match self {
+            TopicSort::Name => TopicSort::Partitions,
+            TopicSort::Partitions => TopicSort::Messages,
+            TopicSort::Messages => TopicSort::Throughput,
+            TopicSort::Throughput => TopicSort::Name,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            TopicSort::Name => "name",
+            TopicSort::Partitions => "partitions",
+            TopicSort::Messages => "messages",
+            TopicSort::Throughput => "throughput",
+        }
+    }
+}
+
+/// Filtering and ordering of the topic list. `visible` holds the indexes in `App::topic_infos`
+/// of the topics to display, in display order.
+pub struct TopicListView {
+    pub query: String,
+    pub editing: bool,
+    pub hide_internal: bool,
+    pub sort: TopicSort,
+    pub ascending: bool,
+    pub visible: Vec<usize>,
+}
+
+impl Default for TopicListView {
+    fn default() -> Self {
+        TopicListView {
+            query: String::new(),
+            editing: false,
+            hide_internal: false,
+            sort: TopicSort::Name,
+            ascending: true,
+            visible: vec![],
+        }
+    }
+}
+
+/// The rows of a table as drawn, with the index of the first one shown.
+#[derive(Default, Clone, Copy)]
+pub struct TableArea {
+    pub rows: Rect,
+    pub offset: usize,
+}
+
+/// Where the parts of the page handling the mouse were last drawn.
+#[derive(Default)]
+pub struct Areas {
+    /// The tabs with the page each one opens.
+    pub tabs: Vec<(Rect, Context)>,
+    pub topic_rows: TableArea,
+    pub group_rows: TableArea,
+    /// Number of lines of the text of the detail page, which bounds its scrolling.
+    pub detail_lines: u16,
 }
 
 pub struct App {
     pub message: String,
-    kafka_wrapper: KafkaWrapper,
+    requests: UnboundedSender<Refresh>,
+    refresh_config: RefreshConfig,
+    /// When the refresh of the current page was last requested, automatically or not.
+    last_requested: Option<Instant>,
+    /// When the data of each kind of page was last received.
+    updated: HashMap<Refresh, Instant>,
+    /// Why the last refresh of each kind of page failed, until its data is received again.
+    failed: HashMap<Refresh, String>,
+    pub paused: bool,
     pub topic_table_state: TableState,
+    pub topic_view: TopicListView,
+    pub group_table_state: TableState,
     pub context: Context,
     pub cluster_info: ClusterInfo,
     pub topic_infos: Vec<TopicInfo>,
     pub group_infos: Vec<GroupInfo>,
     pub selected_topic: Option<String>,
+    pub selected_group: Option<String>,
     pub topic_detail: Option<TopicDetail>,
-    pub offsets: Arc<Mutex<HashMap<OffsetAndMetadata, OffsetValue>>>,
+    pub offsets: Arc<Mutex<OffsetsStore>>,
+    pub cluster_health: ClusterHealth,
+    pub throughput: Throughput,
+    pub lags: LagTracker,
+    pub alerts: Alerts,
+    pub keymap: Keymap,
+    pub theme: Theme,
+    pub show_help: bool,
+    pub should_quit: bool,
+    pub areas: Areas,
+    /// When and which row was last clicked, to detect double clicks.
+    pub last_click: Option<(Instant, usize)>,
+    /// First line shown of the text of the detail page.
+    pub detail_scroll: u16,
 }
 
 impl App {
-    pub fn new(config: &Config) -> App {
-        let kafka_wrapper = KafkaWrapper::new(config.brokers.as_ref());
-        let cluster_info = kafka_wrapper.get_cluster_infos();
-        let topic_infos = kafka_wrapper.get_topic_infos();
-        let group_infos = kafka_wrapper.get_group_infos();
-        let offsets = Arc::new(Mutex::new(HashMap::new()));
-
+    pub fn new(requests: UnboundedSender<Refresh>, refresh_config: RefreshConfig) -> App {
         App {
             message: String::from("Welcome"),
-            kafka_wrapper,
+            requests,
+            refresh_config,
+            last_requested: None,
+            updated: HashMap::new(),
+            failed: HashMap::new(),
+            paused: false,
             topic_table_state: TableState::default(),
+            topic_view: TopicListView::default(),
+            group_table_state: TableState::default(),
             context: TopicListPage,
-            cluster_info,
-            topic_infos,
-            group_infos,
+            cluster_info: ClusterInfo::default(),
+            topic_infos: vec![],
+            group_infos: vec![],
             selected_topic: None,
+            selected_group: None,
             topic_detail: None,
-            offsets,
+            offsets: Arc::new(Mutex::new(OffsetsStore::default())),
+            cluster_health: ClusterHealth::default(),
+            throughput: Throughput::default(),
+            lags: LagTracker::default(),
+            alerts: Alerts::default(),
+            keymap: Keymap::default(),
+            theme: Theme::default(),
+            show_help: false,
+            should_quit: false,
+            areas: Areas::default(),
+            last_click: None,
+            detail_scroll: 0,
+        }
+    }
+
+    fn current_refresh(&self) -> Option<Refresh> {
+        match self.context {
+            TopicListPage => Some(Refresh::TopicList),
+            TopicDetailPage => self.selected_topic.clone().map(Refresh::TopicDetail),
+            GroupListPage | GroupDetailPage => Some(Refresh::GroupList),
+        }
+    }
+
+    /// Ask for fresh data of the current page, applied once its snapshot is received.
+    pub fn refresh(&mut self) {
+        let refresh = match self.current_refresh() {
+            Some(refresh) => refresh,
+            None => return,
+        };
+        self.last_requested = Some(Instant::now());
+        if self.requests.unbounded_send(refresh).is_err() {
+            warn!("The refresher is stopped");
+        }
+    }
+
+    /// Refresh the current page once its interval has elapsed, unless the refresh is paused.
+    pub fn on_tick(&mut self) {
+        self.evaluate_alerts();
+        if self.paused {
+            return;
+        }
+        let interval = self.refresh_config.interval(&self.context);
+        if self
+            .last_requested
+            .is_none_or(|requested| requested.elapsed() >= interval)
+        {
+            self.refresh();
+        }
+    }
+
+    /// Evaluate the alert rules against the data received so far, the lag being unknown while
+    /// the committed offsets are loading.
+    fn evaluate_alerts(&mut self) {
+        let lags: Option<HashMap<(String, String), i64>> = match self.offsets_loading() {
+            Some(_) => None,
+            None => Some(
+                self.lags
+                    .topics
+                    .iter()
+                    .filter_map(|(key, history)| history.last().map(|lag| (key.clone(), lag)))
+                    .collect(),
+            ),
+        };
+        let state = ClusterState {
+            groups: &self.group_infos,
+            topics: &self.topic_infos,
+            lags: lags.as_ref(),
+        };
+        self.alerts.evaluate(&state, now_ms());
+    }
+
+    /// Stop the app loop once the current event is handled.
+    pub fn quit(&mut self) {
+        self.should_quit = true;
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// When the data of the current page was last received.
+    pub fn last_updated(&self) -> Option<Instant> {
+        self.current_refresh()
+            .and_then(|refresh| self.updated.get(&refresh).copied())
+    }
+
+    /// Why the last refresh of the current page failed, its data being the previous one.
+    pub fn refresh_error(&self) -> Option<&str> {
+        self.current_refresh()
+            .and_then(|refresh| self.failed.get(&refresh))
+            .map(String::as_str)
+    }
+
+    pub fn apply_failure(&mut self, refresh: Refresh, error: String) {
+        self.failed.insert(refresh, error);
+    }
+
+    pub fn apply_snapshot(&mut self, snapshot: Snapshot) {
+        let refresh = match &snapshot {
+            Snapshot::TopicList(_) => Refresh::TopicList,
+            Snapshot::TopicDetail { topic, .. } => Refresh::TopicDetail(topic.to_owned()),
+            Snapshot::GroupList(_) => Refresh::GroupList,
+        };
+        self.failed.remove(&refresh);
+        self.updated.insert(refresh, Instant::now());
+        match snapshot {
+            Snapshot::TopicList(snapshot) => self.apply_topic_list(*snapshot),
+            Snapshot::TopicDetail { topic, detail } => self.apply_topic_detail(topic, detail),
+            Snapshot::GroupList(group_infos) => self.apply_group_list(group_infos),
+        }
+    }
+
+    fn apply_topic_list(&mut self, snapshot: TopicListSnapshot) {
+        let selected = self.get_selected_topic().map(|s| s.to_string());
+        self.cluster_info = snapshot.cluster_info;
+        self.group_infos = snapshot.group_infos;
+        self.topic_infos = snapshot.topic_infos;
+        self.update_cluster_health(
+            &snapshot.brokers,
+            &snapshot.partitions,
+            &snapshot.high_watermarks,
+        );
+        self.record_topic_samples();
+        self.record_group_samples();
+        self.apply_topic_view(selected);
+    }
+
+    /// Committed offsets read from `__consumer_offsets`, as (group, topic, partition, offset).
+    fn committed_offsets(&self) -> Vec<(String, String, i32, i64)> {
+        self.offsets.lock().unwrap().committed_offsets()
+    }
+
+    /// Sample the produced offsets of the listed topics to follow their throughput.
+    fn record_topic_samples(&mut self) {
+        let now = Instant::now();
+        for topic in &self.topic_infos {
+            self.throughput
+                .topics
+                .entry(topic.name.to_owned())
+                .or_default()
+                .push(now, topic.high_watermark);
+        }
+    }
+
+    /// Sample the committed offsets of each group per topic to follow their consumption rate.
+    fn record_group_samples(&mut self) {
+        let now = Instant::now();
+        let mut committed: HashMap<(String, String), i64> = HashMap::new();
+        for (group, topic, _, offset) in self.committed_offsets() {
+            *committed.entry((group, topic)).or_insert(0) += offset;
+        }
+        for (key, offset) in committed {
+            self.throughput
+                .groups
+                .entry(key)
+                .or_default()
+                .push(now, offset);
+        }
+    }
+
+    /// Recompute the cluster health from fresh metadata and the committed offsets read so far.
+    fn update_cluster_health(
+        &mut self,
+        brokers: &[i32],
+        partitions: &[PartitionInfo],
+        high_watermarks: &HashMap<(String, i32), i64>,
+    ) {
+        let committed = self.committed_offsets();
+        let committing_groups: HashSet<String> = committed
+            .iter()
+            .map(|(group, _, _, _)| group.to_owned())
+            .collect();
+        self.lags.retain_groups(&committing_groups);
+
+        let group_lags = self.record_lag_samples(committed, high_watermarks);
+        let now = Instant::now();
+        for (group, lag) in group_lags {
+            self.lags.groups.entry(group).or_default().push(now, lag);
+        }
+
+        self.cluster_health = ClusterHealth::compute(
+            brokers,
+            partitions,
+            &self.topic_infos,
+            self.lags.growing_groups(),
+        );
+    }
+
+    /// Sample the lag of the committed offsets whose partition high watermark is known, per
+    /// partition and per group and topic. Returns the total lag of each group over these
+    /// partitions.
+    fn record_lag_samples(
+        &mut self,
+        committed: Vec<(String, String, i32, i64)>,
+        high_watermarks: &HashMap<(String, i32), i64>,
+    ) -> HashMap<String, i64> {
+        // The lag is wrong until the committed offsets are loaded, don't keep it in the history.
+        if self.offsets_loading().is_some() {
+            return HashMap::new();
+        }
+        let now = Instant::now();
+        let mut topic_lags: HashMap<(String, String), i64> = HashMap::new();
+        for (group, topic, partition, offset) in committed {
+            if let Some(high) = high_watermarks.get(&(topic.to_owned(), partition)) {
+                let lag = (high - offset).max(0);
+                *topic_lags
+                    .entry((group.to_owned(), topic.to_owned()))
+                    .or_insert(0) += lag;
+                self.lags
+                    .partitions
+                    .entry((group, topic, partition))
+                    .or_default()
+                    .push(now, lag);
+            }
+        }
+        let mut group_lags: HashMap<String, i64> = HashMap::new();
+        for (key, lag) in topic_lags {
+            *group_lags.entry(key.0.to_owned()).or_insert(0) += lag;
+            self.lags.topics.entry(key).or_default().push(now, lag);
+        }
+        group_lags
+    }
+
+    /// Progress percentage of the initial load of the committed offsets, None once complete.
+    pub fn offsets_loading(&self) -> Option<u8> {
+        let offsets_map = self.offsets.lock().unwrap();
+        if offsets_map.replay.is_complete() {
+            None
+        } else {
+            Some(offsets_map.replay.percentage())
+        }
+    }
+
+    fn apply_topic_detail(&mut self, topic: String, detail: Option<TopicDetail>) {
+        // The answer to a request made before another topic was selected.
+        if self.selected_topic.as_ref() != Some(&topic) {
+            return;
+        }
+        self.topic_detail = detail;
+        if let Some(detail) = &mut self.topic_detail {
+            detail.info.group_count = self
+                .group_infos
+                .iter()
+                .filter(|g| g.consume_topic(&topic))
+                .count();
+            let now = Instant::now();
+            self.throughput
+                .topics
+                .entry(topic.to_owned())
+                .or_default()
+                .push(now, detail.info.high_watermark);
+            for partition in &detail.offsets {
+                self.throughput
+                    .partitions
+                    .entry((topic.to_owned(), partition.id))
+                    .or_default()
+                    .push(now, partition.high);
+            }
+            let high_watermarks: HashMap<(String, i32), i64> = detail
+                .offsets
+                .iter()
+                .map(|p| ((topic.to_owned(), p.id), p.high))
+                .collect();
+            let committed = self
+                .committed_offsets()
+                .into_iter()
+                .filter(|(_, t, _, _)| *t == topic)
+                .collect();
+            self.record_lag_samples(committed, &high_watermarks);
+        }
+        self.record_group_samples();
+    }
+
+    fn apply_group_list(&mut self, group_infos: Vec<GroupInfo>) {
+        let selected = self.get_selected_group().map(|s| s.to_string());
+        self.group_infos = group_infos;
+        let position = selected
+            .and_then(|name| self.group_infos.iter().position(|g| g.name == name))
+            .or(if self.group_infos.is_empty() {
+                None
+            } else {
+                Some(0)
+            });
+        self.group_table_state.select(position);
+        self.record_group_samples();
+    }
+
+    pub fn select_next_group(&mut self) {
+        if self.group_infos.is_empty() {
+            return;
+        }
+        let i = match self.group_table_state.selected() {
+            Some(i) if i < self.group_infos.len() - 1 => i + 1,
+            _ => 0,
+        };
+        self.group_table_state.select(Some(i));
+    }
+
+    pub fn select_previous_group(&mut self) {
+        if self.group_infos.is_empty() {
+            return;
+        }
+        let i = match self.group_table_state.selected() {
+            Some(0) | None => self.group_infos.len() - 1,
+            Some(i) => i - 1,
+        };
+        self.group_table_state.select(Some(i));
+    }
+
+    /// Select the group of a row of the table, returning false when there is no such row.
+    pub fn select_group_row(&mut self, row: usize) -> bool {
+        let exists = row < self.group_infos.len();
+        if exists {
+            self.group_table_state.select(Some(row));
+        }
+        exists
+    }
+
+    pub fn select_first_group(&mut self) {
+        if !self.group_infos.is_empty() {
+            self.group_table_state.select(Some(0));
+        }
+    }
+
+    pub fn select_last_group(&mut self) {
+        if !self.group_infos.is_empty() {
+            self.group_table_state
+                .select(Some(self.group_infos.len() - 1));
+        }
+    }
+
+    pub fn select_current_group(&mut self) {
+        self.selected_group = self.get_selected_group().map(|s| s.to_string());
+        if self.selected_group.is_some() {
+            self.switch_context(GroupDetailPage)
+        }
+    }
+
+    fn get_selected_group(&self) -> Option<&str> {
+        self.group_table_state
+            .selected()
+            .and_then(|i| self.group_infos.get(i))
+            .map(|g| g.name.as_str())
+    }
+
+    /// Switch between the topic and the group lists.
+    pub fn next_tab(&mut self) {
+        match self.context {
+            TopicListPage | TopicDetailPage => self.switch_context(GroupListPage),
+            GroupListPage | GroupDetailPage => self.switch_context(TopicListPage),
+        }
+    }
+
+    /// Recompute the visible topics after a change of the filters or of the sort order. The
+    /// selection follows the selected topic name, not its position in the table.
+    pub fn refresh_topic_view(&mut self) {
+        let selected = self.get_selected_topic().map(|s| s.to_string());
+        self.apply_topic_view(selected);
+    }
+
+    fn apply_topic_view(&mut self, selected: Option<String>) {
+        let view = &self.topic_view;
+        let mut visible: Vec<usize> = self
+            .topic_infos
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !(view.hide_internal && t.is_internal()))
+            .filter(|(_, t)| fuzzy_match(&view.query, &t.name))
+            .map(|(i, _)| i)
+            .collect();
+
+        let topics = &self.topic_infos;
+        let throughput = &self.throughput;
+        visible.sort_by(|&a, &b| {
+            let (a, b) = (&topics[a], &topics[b]);
+            let ordering = match view.sort {
+                TopicSort::Name => a.name.cmp(&b.name),
+                TopicSort::Partitions => a.nb_partitions.cmp(&b.nb_partitions),
+                TopicSort::Messages => a.message_count.cmp(&b.message_count),
+                TopicSort::Throughput => throughput
+                    .topic_rate(&a.name)
+                    .partial_cmp(&throughput.topic_rate(&b.name))
+                    .unwrap_or(Ordering::Equal),
+            }
+            .then_with(|| a.name.cmp(&b.name));
+            if view.ascending {
+                ordering
+            } else {
+                ordering.reverse()
+            }
+        });
+
+        let position = selected
+            .and_then(|name| visible.iter().position(|&i| topics[i].name == name))
+            .or(if visible.is_empty() { None } else { Some(0) });
+        self.topic_view.visible = visible;
+        self.topic_table_state.select(position);
+    }
+
+    pub fn start_topic_search(&mut self) {
+        self.topic_view.editing = true;
+    }
+
+    /// Leave the search bar, keeping the current filter unless `clear` is set.
+    pub fn stop_topic_search(&mut self, clear: bool) {
+        self.topic_view.editing = false;
+        if clear {
+            self.topic_view.query.clear();
+            self.refresh_topic_view();
         }
     }
 
-    fn load_topic_list(&mut self) {
-        self.cluster_info = self.kafka_wrapper.get_cluster_infos();
-        self.topic_infos = self.kafka_wrapper.get_topic_infos()
+    pub fn push_topic_search(&mut self, c: char) {
+        self.topic_view.query.push(c);
+        self.refresh_topic_view();
+    }
+
+    pub fn pop_topic_search(&mut self) {
+        self.topic_view.query.pop();
+        self.refresh_topic_view();
+    }
+
+    pub fn toggle_internal_topics(&mut self) {
+        self.topic_view.hide_internal = !self.topic_view.hide_internal;
+        self.refresh_topic_view();
     }
 
-    pub fn load_topic_detail(&mut self) {
-        let topic = self.selected_topic.as_ref().unwrap();
-        self.topic_detail = self.kafka_wrapper.get_topic_detail(topic.as_str());
+    pub fn cycle_topic_sort(&mut self) {
+        self.topic_view.sort = self.topic_view.sort.next();
+        self.refresh_topic_view();
     }
 
-    pub fn change_message(&mut self, new_message: String) {
-        self.message = new_message;
+    pub fn reverse_topic_sort(&mut self) {
+        self.topic_view.ascending = !self.topic_view.ascending;
+        self.refresh_topic_view();
+    }
+
+    /// True when the keyboard is captured by a text input, so that the keys are not bound to
+    /// actions.
+    pub fn is_editing(&self) -> bool {
+        self.topic_view.editing
     }
 
     pub fn select_next_topic(&mut self) {
+        if self.topic_view.visible.is_empty() {
+            return;
+        }
         let i = match self.topic_table_state.selected() {
             Some(i) => {
-                if i >= self.topic_infos.len() - 1 {
+                if i >= self.topic_view.visible.len() - 1 {
                     0
                 } else {
                     i + 1
@@ -97,10 +633,13 @@ impl App {
     }
 
     pub fn select_previous_topic(&mut self) {
+        if self.topic_view.visible.is_empty() {
+            return;
+        }
         let i = match self.topic_table_state.selected() {
             Some(i) => {
                 if i == 0 {
-                    self.topic_infos.len() - 1
+                    self.topic_view.visible.len() - 1
                 } else {
                     i - 1
                 }
@@ -111,44 +650,88 @@ impl App {
     }
 
     pub fn switch_context(&mut self, context: Context) {
-        match context {
-            TopicListPage => self.load_topic_list(),
-            TopicDetailPage => self.load_topic_detail(),
+        self.context = context;
+        self.detail_scroll = 0;
+        self.last_click = None;
+        self.refresh();
+    }
+
+    /// Scroll the text of the detail page by a line, until its last line is the first shown.
+    pub fn scroll_detail(&mut self, down: bool) {
+        self.detail_scroll = if down {
+            (self.detail_scroll + 1).min(self.areas.detail_lines.saturating_sub(1))
+        } else {
+            self.detail_scroll.saturating_sub(1)
+        };
+    }
+
+    /// Select the topic of a row of the table, returning false when there is no such row.
+    pub fn select_topic_row(&mut self, row: usize) -> bool {
+        let exists = row < self.topic_view.visible.len();
+        if exists {
+            self.topic_table_state.select(Some(row));
+        }
+        exists
+    }
+
+    pub fn select_first_topic(&mut self) {
+        if !self.topic_view.visible.is_empty() {
+            self.topic_table_state.select(Some(0));
+        }
+    }
+
+    pub fn select_last_topic(&mut self) {
+        if !self.topic_view.visible.is_empty() {
+            self.topic_table_state
+                .select(Some(self.topic_view.visible.len() - 1));
         }
-        self.context = context
     }
 
     pub fn select_current_topic(&mut self) {
         self.selected_topic = self.get_selected_topic().map(|s| s.to_string());
-        self.switch_context(TopicDetailPage)
+        if self.selected_topic.is_some() {
+            self.topic_detail = None;
+            self.switch_context(TopicDetailPage)
+        }
     }
 
     fn get_selected_topic(&self) -> Option<&str> {
         self.topic_table_state
             .selected()
-            .map(|i| self.topic_infos[i].name.borrow())
+            .and_then(|i| self.topic_view.visible.get(i))
+            .map(|&i| self.topic_infos[i].name.borrow())
     }
 }
 
 #[tokio::main]
-pub async fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let mut app = App::new(&config);
+pub async fn run(config: Config, keymap: Keymap) -> Result<(), Box<dyn Error>> {
+    // Before the terminal is taken over, for the errors to be readable.
+    let client = KafkaWrapper::new(&config.brokers)?;
+    let (requests, requests_rx) = unbounded();
+    let mut app = App::new(requests, config.refresh.clone());
+    let offsets_source = build_offsets_source(&config, Arc::clone(&app.offsets))?;
+    app.alerts = Alerts::new(config.alerts.rules.clone());
+    app.keymap = keymap;
+    app.theme = Theme::new(&config.theme, no_color_requested());
 
-    // Definition of the event channel. An event is triggered by tick time or by a user keyboard
-    // input
+    // Definition of the event channel. An event is triggered by tick time, by a user keyboard
+    // input or by fresh data from the refresher
     let (tx, rx) = mpsc::channel();
+    let refresher_tx = tx.clone();
 
-    //TODO tick-rate from conf
-    let tick_rate = Duration::from_millis(5000);
+    // The pages are refreshed at their own intervals, see `App::on_tick`, the ticks only set the
+    // precision of these intervals and of the "updated N s ago" display
+    let tick_rate = Duration::from_millis(1000);
     thread::spawn(move || {
         let mut last_tick = Instant::now();
-        let timeout = tick_rate
-            .checked_sub(last_tick.elapsed())
-            .unwrap_or_else(|| Duration::from_secs(0));
         loop {
+            let timeout = tick_rate
+                .checked_sub(last_tick.elapsed())
+                .unwrap_or_else(|| Duration::from_secs(0));
             if poll(timeout).unwrap() {
                 match read().unwrap() {
                     CEvent::Key(event) => tx.send(Event::Input(event.code)).unwrap(),
+                    CEvent::Mouse(event) => tx.send(Event::Mouse(event)).unwrap(),
                     _ => warn!("other event"),
                 }
             }
@@ -169,39 +752,425 @@ pub async fn run(config: Config) -> Result<(), Box<dyn Error>> {
     let mut terminal = Terminal::new(backend)?;
     terminal.clear()?;
 
-    // Spawn a thread for the offsets consumer
-    let db = Arc::clone(&app.offsets);
-    tokio::spawn(OffsetsConsumer::get_consumer_group_offsets(
-        Arc::new(config.brokers),
-        db,
-    ));
+    // Spawn a task for the offsets source
+    tokio::spawn(offsets_source.run());
+
+    // Spawn a task fetching the data of the pages
+    let refresher = Refresher::new(
+        Box::new(client),
+        Arc::clone(&app.offsets),
+        requests_rx,
+        refresher_tx,
+    );
+    tokio::spawn(refresher.run());
+    app.refresh();
 
     // app loop. Wait for some event, then draw the terminal
     loop {
-        terminal.draw(|f| match &app.context {
-            TopicListPage => ui::draw(f, &mut app),
-            TopicDetailPage => ui::draw_topic_detail(f, &app),
-        })?;
-
-        let event = rx.recv()?;
-        match event {
-            Event::Input(key) => match key {
-                // If the user use 'q', quit the app, else redirect the events to the current
-                // context page.
-                KeyCode::Char('q') => {
-                    disable_raw_mode()?;
-                    execute!(
-                        terminal.backend_mut(),
-                        LeaveAlternateScreen,
-                        DisableMouseCapture
-                    )?;
-                    terminal.show_cursor()?;
-                    break;
+        terminal.draw(|f| ui::draw(f, &mut app))?;
+
+        match rx.recv()? {
+            Event::Input(key) => handle_key(key, &mut app),
+            Event::Mouse(event) => handle_mouse(event, &mut app),
+            Event::Tick => app.on_tick(),
+            Event::Refreshed(snapshot) => app.apply_snapshot(snapshot),
+            Event::RefreshFailed(refresh, error) => app.apply_failure(refresh, error),
+        }
+        if app.should_quit {
+            disable_raw_mode()?;
+            execute!(
+                terminal.backend_mut(),
+                LeaveAlternateScreen,
+                DisableMouseCapture
+            )?;
+            terminal.show_cursor()?;
+            break;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{AlertCondition, AlertRule};
+    use crate::fake_cluster::FakeCluster;
+    use crate::refresher::fetch;
+    use crossterm::event::{KeyCode, KeyModifiers, MouseButton, MouseEvent};
+    use futures::channel::mpsc::UnboundedReceiver;
+    use tui::backend::TestBackend;
+
+    /// An `App` whose refresh requests are served synchronously by a fake cluster.
+    struct Harness {
+        app: App,
+        requests: UnboundedReceiver<Refresh>,
+        cluster: FakeCluster,
+    }
+
+    impl Harness {
+        fn new(cluster: FakeCluster) -> Harness {
+            Harness::with_config(cluster, RefreshConfig::default())
+        }
+
+        fn with_config(cluster: FakeCluster, refresh_config: RefreshConfig) -> Harness {
+            let (requests, requests_rx) = unbounded();
+            Harness {
+                app: App::new(requests, refresh_config),
+                requests: requests_rx,
+                cluster,
+            }
+        }
+
+        /// Answer the pending requests, returning how many there were.
+        fn serve(&mut self) -> usize {
+            let mut served = 0;
+            while let Ok(Some(refresh)) = self.requests.try_next() {
+                match fetch(&self.cluster, &self.app.offsets, refresh.clone()) {
+                    Ok(snapshot) => self.app.apply_snapshot(snapshot),
+                    Err(e) => self.app.apply_failure(refresh, e.to_string()),
                 }
-                _ => handle_event(event, &mut app),
+                served += 1;
+            }
+            served
+        }
+
+        fn commit(&self, group: &str, topic: &str, partition: i32, offset: i64) {
+            self.app
+                .offsets
+                .lock()
+                .unwrap()
+                .commit(group, topic, partition, offset);
+        }
+
+        fn finish_offsets_load(&self) {
+            self.app.offsets.lock().unwrap().replay.finish();
+        }
+
+        fn topic_lag(&self, group: &str, topic: &str) -> Option<i64> {
+            self.app.lags.topic_lag(group, topic).and_then(|h| h.last())
+        }
+    }
+
+    #[test]
+    fn loads_the_topic_list() {
+        let mut h = Harness::new(FakeCluster::sample());
+        assert!(h.app.last_updated().is_none());
+
+        h.app.refresh();
+        assert_eq!(h.serve(), 1);
+
+        assert!(h.app.last_updated().is_some());
+        assert_eq!(h.app.cluster_info.broker_count, 2);
+        assert_eq!(h.app.topic_infos.len(), 2);
+        assert_eq!(h.app.group_infos.len(), 1);
+        assert_eq!(h.app.topic_view.visible.len(), 2);
+        assert_eq!(h.app.get_selected_topic(), Some("orders"));
+        let orders = &h.app.topic_infos[0];
+        assert_eq!(orders.group_count, 1);
+        assert_eq!(orders.replication_factor, 2);
+    }
+
+    #[test]
+    fn keeps_the_data_of_a_failed_refresh() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.app.refresh();
+        h.serve();
+        let updated = h.app.last_updated();
+
+        h.cluster.unreachable = true;
+        h.app.refresh();
+        assert_eq!(h.serve(), 1);
+        assert_eq!(h.app.topic_infos.len(), 2);
+        assert_eq!(h.app.last_updated(), updated);
+        let error = h.app.refresh_error().unwrap();
+        assert!(
+            error.contains("All broker connections are down"),
+            "{}",
+            error
+        );
+
+        h.cluster.unreachable = false;
+        h.app.refresh();
+        h.serve();
+        assert!(h.app.refresh_error().is_none());
+        assert!(h.app.last_updated() > updated);
+    }
+
+    #[test]
+    fn opens_the_detail_of_the_selected_topic() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.cluster.produce("orders", 1, 42);
+        h.app.refresh();
+        h.serve();
+
+        h.app.select_current_topic();
+        assert!(matches!(h.app.context, TopicDetailPage));
+        assert!(h.app.topic_detail.is_none());
+        assert!(h.app.last_updated().is_none());
+
+        assert_eq!(h.serve(), 1);
+        let detail = h.app.topic_detail.as_ref().unwrap();
+        assert_eq!(detail.info.name, "orders");
+        assert_eq!(detail.message_count, 42);
+        assert_eq!(detail.offsets.len(), 2);
+        // Counted by the `App`, from the groups of the topic list.
+        assert_eq!(detail.info.group_count, 1);
+        assert!(h.app.last_updated().is_some());
+    }
+
+    #[test]
+    fn ignores_the_detail_of_a_topic_no_longer_selected() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.app.refresh();
+        h.serve();
+        h.app.select_current_topic();
+
+        h.app.context = TopicListPage;
+        h.app.select_next_topic();
+        h.app.select_current_topic();
+        assert_eq!(h.app.selected_topic.as_deref(), Some("payments"));
+
+        assert_eq!(h.serve(), 2);
+        assert_eq!(h.app.topic_detail.as_ref().unwrap().info.name, "payments");
+    }
+
+    #[test]
+    fn computes_the_lag_of_the_committed_offsets() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.cluster.produce("orders", 0, 100);
+        h.cluster.produce("orders", 1, 50);
+        h.commit("billing", "orders", 0, 80);
+        h.commit("billing", "orders", 1, 50);
+        h.finish_offsets_load();
+
+        h.app.refresh();
+        h.serve();
+
+        assert_eq!(h.topic_lag("billing", "orders"), Some(20));
+        let lags = &h.app.lags;
+        let partition_lag = |p| {
+            lags.partitions
+                .get(&("billing".to_owned(), "orders".to_owned(), p))
+                .and_then(|h| h.last())
+        };
+        assert_eq!(partition_lag(0), Some(20));
+        assert_eq!(partition_lag(1), Some(0));
+        assert_eq!(lags.groups.get("billing").and_then(|h| h.last()), Some(20));
+    }
+
+    #[test]
+    fn raises_lag_alerts_on_tick() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.app.alerts = Alerts::new(vec![AlertRule {
+            name: String::from("billing-lag"),
+            condition: AlertCondition::GroupLag {
+                group: String::from("billing"),
+                topic: Some(String::from("orders")),
+                max_lag: 10,
             },
-            Event::Tick => handle_event(event, &mut app),
+            for_ms: 0,
+        }]);
+        h.cluster.produce("orders", 0, 100);
+        h.commit("billing", "orders", 0, 80);
+        h.finish_offsets_load();
+        h.app.on_tick();
+        assert!(h.app.alerts.firing().is_empty());
+
+        h.serve();
+        h.app.on_tick();
+        let firing = h.app.alerts.firing();
+        assert_eq!(firing.len(), 1);
+        assert_eq!(firing[0].subject, "billing on orders");
+        assert_eq!(firing[0].message, "lag 20 > 10");
+
+        // A new replay of the offsets doesn't resolve the alert.
+        *h.app.offsets.lock().unwrap() = OffsetsStore::default();
+        h.app.refresh();
+        h.serve();
+        h.app.on_tick();
+        assert!(h.app.lags.topics.is_empty());
+        assert_eq!(h.app.alerts.firing().len(), 1);
+    }
+
+    #[test]
+    fn computes_no_lag_while_the_offsets_are_loading() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.cluster.produce("orders", 0, 100);
+        h.commit("billing", "orders", 0, 80);
+
+        h.app.refresh();
+        h.serve();
+
+        assert!(h.app.offsets_loading().is_some());
+        assert!(h.app.lags.topics.is_empty());
+        assert!(h.app.lags.groups.is_empty());
+    }
+
+    #[test]
+    fn forgets_the_lag_of_deleted_groups() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.cluster.produce("orders", 0, 100);
+        h.commit("billing", "orders", 0, 80);
+        h.finish_offsets_load();
+        h.app.refresh();
+        h.serve();
+        assert_eq!(h.topic_lag("billing", "orders"), Some(20));
+
+        h.app.offsets.lock().unwrap().offsets.clear();
+        h.cluster.groups.clear();
+        h.app.refresh();
+        h.serve();
+
+        assert!(h.topic_lag("billing", "orders").is_none());
+        assert!(h.app.group_infos.is_empty());
+    }
+
+    #[test]
+    fn reports_under_replicated_partitions() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.cluster.partition_mut("orders", 1).isr = vec![2];
+        h.app.refresh();
+        h.serve();
+
+        assert_eq!(h.app.topic_infos[0].under_replicated, 1);
+        assert_eq!(h.app.cluster_health.under_replicated, 1);
+        assert_eq!(h.app.cluster_health.offline, 0);
+    }
+
+    #[test]
+    fn refreshes_on_tick_unless_paused() {
+        let refresh_config = RefreshConfig {
+            interval_ms: 0,
+            ..RefreshConfig::default()
+        };
+        let mut h = Harness::with_config(FakeCluster::sample(), refresh_config);
+
+        h.app.on_tick();
+        assert_eq!(h.serve(), 1);
+
+        h.app.toggle_pause();
+        h.app.on_tick();
+        assert_eq!(h.serve(), 0);
+
+        h.app.toggle_pause();
+        h.app.on_tick();
+        assert_eq!(h.serve(), 1);
+    }
+
+    #[test]
+    fn keeps_the_selected_topic_while_filtering() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.app.refresh();
+        h.serve();
+        h.app.select_next_topic();
+        assert_eq!(h.app.get_selected_topic(), Some("payments"));
+
+        h.app.push_topic_search('p');
+        assert_eq!(h.app.get_selected_topic(), Some("payments"));
+
+        h.app.pop_topic_search();
+        h.app.push_topic_search('o');
+        h.app.push_topic_search('r');
+        assert_eq!(h.app.topic_view.visible.len(), 1);
+        assert_eq!(h.app.get_selected_topic(), Some("orders"));
+
+        h.app.refresh();
+        h.serve();
+        assert_eq!(h.app.get_selected_topic(), Some("orders"));
+    }
+
+    #[test]
+    fn handles_the_help_and_the_global_keys() {
+        let mut h = Harness::new(FakeCluster::sample());
+        handle_key(KeyCode::Char('?'), &mut h.app);
+        assert!(h.app.show_help);
+
+        // Any key closes the help, without doing what it is bound to.
+        handle_key(KeyCode::Char('p'), &mut h.app);
+        assert!(!h.app.show_help);
+        assert!(!h.app.paused);
+
+        handle_key(KeyCode::Char('p'), &mut h.app);
+        assert!(h.app.paused);
+
+        // The keys typed in the topic filter are text.
+        handle_key(KeyCode::Char('/'), &mut h.app);
+        handle_key(KeyCode::Char('q'), &mut h.app);
+        assert_eq!(h.app.topic_view.query, "q");
+        assert!(!h.app.should_quit);
+
+        handle_key(KeyCode::Enter, &mut h.app);
+        handle_key(KeyCode::Char('?'), &mut h.app);
+        handle_key(KeyCode::Char('q'), &mut h.app);
+        assert!(h.app.should_quit);
+    }
+
+    /// Draw the app, which records where the parts handling the mouse are.
+    fn draw(app: &mut App) {
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|f| ui::draw(f, app)).unwrap();
+    }
+
+    fn click(column: u16, row: u16) -> MouseEvent {
+        MouseEvent::Down(MouseButton::Left, column, row, KeyModifiers::empty())
+    }
+
+    #[test]
+    fn selects_and_opens_the_clicked_rows() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.app.refresh();
+        h.serve();
+        draw(&mut h.app);
+        let rows = h.app.areas.topic_rows.rows;
+
+        handle_mouse(click(rows.x + 4, rows.y + 1), &mut h.app);
+        assert_eq!(h.app.get_selected_topic(), Some("payments"));
+
+        // Below the last topic.
+        handle_mouse(click(rows.x + 4, rows.y + 2), &mut h.app);
+        assert_eq!(h.app.get_selected_topic(), Some("payments"));
+
+        handle_mouse(click(rows.x + 4, rows.y), &mut h.app);
+        handle_mouse(click(rows.x + 4, rows.y), &mut h.app);
+        assert!(matches!(h.app.context, TopicDetailPage));
+        assert_eq!(h.app.selected_topic.as_deref(), Some("orders"));
+    }
+
+    #[test]
+    fn switches_the_clicked_tabs() {
+        let mut h = Harness::new(FakeCluster::sample());
+        draw(&mut h.app);
+        let (groups, _) = h.app.areas.tabs[1];
+
+        handle_mouse(click(groups.x, groups.y), &mut h.app);
+        assert!(matches!(h.app.context, GroupListPage));
+
+        draw(&mut h.app);
+        let (topics, _) = h.app.areas.tabs[0];
+        handle_mouse(click(topics.right() - 1, topics.y), &mut h.app);
+        assert!(matches!(h.app.context, TopicListPage));
+    }
+
+    #[test]
+    fn scrolls_with_the_wheel() {
+        let mut h = Harness::new(FakeCluster::sample());
+        h.app.refresh();
+        h.serve();
+        let wheel_down = MouseEvent::ScrollDown(0, 0, KeyModifiers::empty());
+        let wheel_up = MouseEvent::ScrollUp(0, 0, KeyModifiers::empty());
+
+        assert_eq!(h.app.get_selected_topic(), Some("orders"));
+        handle_mouse(wheel_down, &mut h.app);
+        assert_eq!(h.app.get_selected_topic(), Some("payments"));
+
+        h.app.select_current_topic();
+        h.serve();
+        draw(&mut h.app);
+        for _ in 0..100 {
+            handle_mouse(wheel_down, &mut h.app);
         }
+        assert_eq!(h.app.detail_scroll, h.app.areas.detail_lines - 1);
+        handle_mouse(wheel_up, &mut h.app);
+        assert_eq!(h.app.detail_scroll, h.app.areas.detail_lines - 2);
     }
-    Ok(())
 }
@@ -0,0 +1,699 @@
+use crate::cluster::ClusterClient;
+use crate::lag_check;
+use crate::model::{ClusterHealth, GroupInfo};
+use clap::{App as ClapApp, AppSettings, Arg, ArgMatches, SubCommand};
+use rdkafka::error::KafkaResult;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How the result of a command is printed.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    Table,
+    Json,
+    Yaml,
+}
+
+impl OutputFormat {
+    fn from_args(args: &ArgMatches) -> OutputFormat {
+        match args.value_of("output") {
+            Some("json") => OutputFormat::Json,
+            Some("yaml") => OutputFormat::Yaml,
+            _ => OutputFormat::Table,
+        }
+    }
+}
+
+pub fn output_arg() -> Arg<'static, 'static> {
+    Arg::with_name("output")
+        .short("o")
+        .long("output")
+        .help("output format of the commands")
+        .takes_value(true)
+        .possible_values(&["table", "json", "yaml"])
+        .default_value("table")
+        .global(true)
+}
+
+/// Commands printing the state of the cluster instead of starting the UI.
+pub fn subcommands() -> Vec<ClapApp<'static, 'static>> {
+    vec![
+        SubCommand::with_name("topics")
+            .about("Topics of the cluster")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("list").about("List the topics")),
+        SubCommand::with_name("topic")
+            .about("A topic of the cluster")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("describe")
+                    .about("Describe the partitions and the consumers of a topic")
+                    .arg(Arg::with_name("name").required(true)),
+            ),
+        SubCommand::with_name("groups")
+            .about("Consumer groups of the cluster")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("list").about("List the consumer groups")),
+        SubCommand::with_name("group")
+            .about("A consumer group of the cluster")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(
+                SubCommand::with_name("describe")
+                    .about("Describe the members, the committed offsets and the lag of a group")
+                    .arg(Arg::with_name("name").required(true)),
+            ),
+        SubCommand::with_name("brokers").about("List the brokers and the partitions they lead"),
+        lag_check::subcommand(),
+    ]
+}
+
+/// What a command prints on stdout, and the code the process exits with.
+pub struct CommandOutput {
+    pub text: String,
+    pub exit_code: i32,
+}
+
+/// Run the command of `args` against the cluster, returning its output, or the error to report.
+pub fn run(client: &dyn ClusterClient, args: &ArgMatches) -> Result<CommandOutput, String> {
+    if let ("lag", Some(args)) = args.subcommand() {
+        return match args.subcommand() {
+            // A check which fails is reported on stdout as UNKNOWN, like monitoring systems expect.
+            ("check", Some(args)) => match lag_check::run(client, args) {
+                Ok(report) => Ok(CommandOutput {
+                    text: render(args, &report)?,
+                    exit_code: report.exit_code(),
+                }),
+                Err(e) => Ok(CommandOutput {
+                    text: format!("UNKNOWN: {}\n", e),
+                    exit_code: lag_check::UNKNOWN_EXIT_CODE,
+                }),
+            },
+            _ => Err(args.usage().to_owned()),
+        };
+    }
+    describe(client, args).map(|text| CommandOutput { text, exit_code: 0 })
+}
+
+fn describe(client: &dyn ClusterClient, args: &ArgMatches) -> Result<String, String> {
+    match args.subcommand() {
+        ("topics", Some(args)) => match args.subcommand() {
+            ("list", Some(args)) => render(args, &list_topics(client).map_err(|e| e.to_string())?),
+            _ => Err(args.usage().to_owned()),
+        },
+        ("topic", Some(args)) => match args.subcommand() {
+            ("describe", Some(args)) => {
+                let name = args.value_of("name").unwrap();
+                render(args, &describe_topic(client, name)?)
+            }
+            _ => Err(args.usage().to_owned()),
+        },
+        ("groups", Some(args)) => match args.subcommand() {
+            ("list", Some(args)) => render(args, &list_groups(client).map_err(|e| e.to_string())?),
+            _ => Err(args.usage().to_owned()),
+        },
+        ("group", Some(args)) => match args.subcommand() {
+            ("describe", Some(args)) => {
+                let name = args.value_of("name").unwrap();
+                render(args, &describe_group(client, name)?)
+            }
+            _ => Err(args.usage().to_owned()),
+        },
+        ("brokers", Some(args)) => render(args, &list_brokers(client).map_err(|e| e.to_string())?),
+        _ => Err(args.usage().to_owned()),
+    }
+}
+
+/// The result of a command, serialized as is in JSON and YAML.
+pub trait Report: Serialize {
+    fn to_table(&self) -> String;
+}
+
+fn render<R: Report>(args: &ArgMatches, report: &R) -> Result<String, String> {
+    match OutputFormat::from_args(args) {
+        OutputFormat::Table => Ok(report.to_table()),
+        OutputFormat::Json => serde_json::to_string_pretty(report)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string()),
+        OutputFormat::Yaml => serde_yaml::to_string(report).map_err(|e| e.to_string()),
+    }
+}
+
+/// Columns left aligned on the widest value, separated by two spaces.
+pub fn format_table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.len()).collect();
+    for row in rows {
+        for (width, value) in widths.iter_mut().zip(row) {
+            *width = (*width).max(value.chars().count());
+        }
+    }
+    let format_row = |values: Vec<&str>| -> String {
+        let cells: Vec<String> = values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:width$}", value, width = width))
+            .collect();
+        cells.join("  ").trim_end().to_owned() + "\n"
+    };
+    let mut table = format_row(headers.to_vec());
+    for row in rows {
+        table.push_str(&format_row(row.iter().map(String::as_str).collect()));
+    }
+    table
+}
+
+fn format_ids(ids: &[i32]) -> String {
+    let ids: Vec<String> = ids.iter().map(i32::to_string).collect();
+    ids.join(",")
+}
+
+#[derive(Serialize)]
+struct TopicSummary {
+    name: String,
+    partitions: usize,
+    replication_factor: usize,
+    messages: i64,
+    cleanup_policy: String,
+    consumer_groups: usize,
+    under_replicated_partitions: usize,
+}
+
+fn list_topics(client: &dyn ClusterClient) -> KafkaResult<Vec<TopicSummary>> {
+    let groups = client.get_group_infos()?;
+    let mut topics: Vec<TopicSummary> = client
+        .get_topic_infos(&groups)?
+        .into_iter()
+        .map(|t| TopicSummary {
+            name: t.name,
+            partitions: t.nb_partitions,
+            replication_factor: t.replication_factor,
+            messages: t.message_count,
+            cleanup_policy: t.config.cleanup_policy,
+            consumer_groups: t.group_count,
+            under_replicated_partitions: t.under_replicated,
+        })
+        .collect();
+    topics.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(topics)
+}
+
+impl Report for Vec<TopicSummary> {
+    fn to_table(&self) -> String {
+        let rows: Vec<Vec<String>> = self
+            .iter()
+            .map(|t| {
+                vec![
+                    t.name.to_owned(),
+                    t.partitions.to_string(),
+                    t.replication_factor.to_string(),
+                    t.messages.to_string(),
+                    t.cleanup_policy.to_owned(),
+                    t.consumer_groups.to_string(),
+                    t.under_replicated_partitions.to_string(),
+                ]
+            })
+            .collect();
+        format_table(
+            &[
+                "NAME",
+                "PARTITIONS",
+                "RF",
+                "MESSAGES",
+                "CLEANUP",
+                "GROUPS",
+                "UNDER-REPLICATED",
+            ],
+            &rows,
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct PartitionDescription {
+    id: i32,
+    leader: i32,
+    replicas: Vec<i32>,
+    isr: Vec<i32>,
+    low: i64,
+    high: i64,
+    messages: i64,
+}
+
+#[derive(Serialize)]
+struct TopicDescription {
+    name: String,
+    replication_factor: usize,
+    cleanup_policy: String,
+    min_insync_replicas: usize,
+    messages: i64,
+    partitions: Vec<PartitionDescription>,
+    consumer_groups: Vec<String>,
+}
+
+fn describe_topic(client: &dyn ClusterClient, name: &str) -> Result<TopicDescription, String> {
+    let groups = client.get_group_infos().map_err(|e| e.to_string())?;
+    let detail = client
+        .get_topic_detail(name, &groups)
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("No topic {}", name))?;
+    let replicas: HashMap<i32, (Vec<i32>, Vec<i32>)> = client
+        .get_partition_infos()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|p| p.topic == name)
+        .map(|p| (p.id, (p.replicas, p.isr)))
+        .collect();
+    let partitions = detail
+        .offsets
+        .iter()
+        .map(|p| {
+            let (replicas, isr) = replicas.get(&p.id).cloned().unwrap_or_default();
+            PartitionDescription {
+                id: p.id,
+                leader: p.leader,
+                replicas,
+                isr,
+                low: p.low,
+                high: p.high,
+                messages: p.count,
+            }
+        })
+        .collect();
+    Ok(TopicDescription {
+        name: detail.info.name,
+        replication_factor: detail.info.replication_factor,
+        cleanup_policy: detail.info.config.cleanup_policy,
+        min_insync_replicas: detail.info.config.min_insync_replicas,
+        messages: detail.message_count,
+        partitions,
+        consumer_groups: groups
+            .iter()
+            .filter(|g| g.consume_topic(name))
+            .map(|g| g.name.to_owned())
+            .collect(),
+    })
+}
+
+impl Report for TopicDescription {
+    fn to_table(&self) -> String {
+        let mut text = format!(
+            "Name: {}\nReplication factor: {}\nCleanup policy: {}\nMin in-sync replicas: {}\n\
+             Messages: {}\nConsumer groups: {}\n\n",
+            self.name,
+            self.replication_factor,
+            self.cleanup_policy,
+            self.min_insync_replicas,
+            self.messages,
+            self.consumer_groups.join(", ")
+        );
+        let rows: Vec<Vec<String>> = self
+            .partitions
+            .iter()
+            .map(|p| {
+                vec![
+                    p.id.to_string(),
+                    p.leader.to_string(),
+                    format_ids(&p.replicas),
+                    format_ids(&p.isr),
+                    p.low.to_string(),
+                    p.high.to_string(),
+                    p.messages.to_string(),
+                ]
+            })
+            .collect();
+        text.push_str(&format_table(
+            &[
+                "PARTITION",
+                "LEADER",
+                "REPLICAS",
+                "ISR",
+                "LOW",
+                "HIGH",
+                "MESSAGES",
+            ],
+            &rows,
+        ));
+        text
+    }
+}
+
+#[derive(Serialize)]
+struct GroupSummary {
+    name: String,
+    state: String,
+    protocol_type: String,
+    protocol: String,
+    members: usize,
+}
+
+fn list_groups(client: &dyn ClusterClient) -> KafkaResult<Vec<GroupSummary>> {
+    Ok(client
+        .get_group_infos()?
+        .into_iter()
+        .map(|g| GroupSummary {
+            members: g.members.len(),
+            name: g.name,
+            state: g.state,
+            protocol_type: g.protocol_type,
+            protocol: g.protocol,
+        })
+        .collect())
+}
+
+impl Report for Vec<GroupSummary> {
+    fn to_table(&self) -> String {
+        let rows: Vec<Vec<String>> = self
+            .iter()
+            .map(|g| {
+                vec![
+                    g.name.to_owned(),
+                    g.state.to_owned(),
+                    g.protocol_type.to_owned(),
+                    g.protocol.to_owned(),
+                    g.members.to_string(),
+                ]
+            })
+            .collect();
+        format_table(
+            &["NAME", "STATE", "PROTOCOL TYPE", "PROTOCOL", "MEMBERS"],
+            &rows,
+        )
+    }
+}
+
+#[derive(Serialize)]
+struct MemberDescription {
+    id: String,
+    client_id: String,
+    host: String,
+    assignments: Vec<String>,
+}
+
+#[derive(Serialize)]
+pub struct PartitionLag {
+    pub topic: String,
+    pub partition: i32,
+    pub committed: i64,
+    /// Unknown when the partition does not exist anymore.
+    pub high_watermark: Option<i64>,
+    pub lag: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct GroupDescription {
+    name: String,
+    state: String,
+    protocol_type: String,
+    protocol: String,
+    members: Vec<MemberDescription>,
+    lag: i64,
+    offsets: Vec<PartitionLag>,
+}
+
+/// Lag of the committed offsets of a group, ordered by topic and partition.
+pub fn group_lag(client: &dyn ClusterClient, group: &str) -> KafkaResult<Vec<PartitionLag>> {
+    let committed = client.get_committed_offsets(group)?;
+    let partitions: Vec<(String, i32)> = committed.keys().cloned().collect();
+    let high_watermarks = client.get_high_watermarks(&partitions)?;
+    let mut lags: Vec<PartitionLag> = committed
+        .into_iter()
+        .map(|((topic, partition), offset)| {
+            let high_watermark = high_watermarks.get(&(topic.to_owned(), partition)).copied();
+            PartitionLag {
+                lag: high_watermark.map(|high| (high - offset).max(0)),
+                topic,
+                partition,
+                committed: offset,
+                high_watermark,
+            }
+        })
+        .collect();
+    lags.sort_by(|a, b| (&a.topic, a.partition).cmp(&(&b.topic, b.partition)));
+    Ok(lags)
+}
+
+fn describe_group(client: &dyn ClusterClient, name: &str) -> Result<GroupDescription, String> {
+    let group: Option<GroupInfo> = client
+        .get_group_infos()
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .find(|g| g.name == name);
+    let offsets = group_lag(client, name).map_err(|e| e.to_string())?;
+    // A group without members nor committed offsets is unknown to the cluster.
+    let group = match group {
+        Some(group) => group,
+        None if !offsets.is_empty() => GroupInfo {
+            name: name.to_owned(),
+            state: String::from("Unknown"),
+            protocol_type: String::new(),
+            protocol: String::new(),
+            members: vec![],
+        },
+        None => return Err(format!("No group {}", name)),
+    };
+    let members = group
+        .members
+        .iter()
+        .map(|m| MemberDescription {
+            id: m.id.to_owned(),
+            client_id: m.client_id.to_owned(),
+            host: m.client_host.to_owned(),
+            assignments: m
+                .protocol
+                .assignments()
+                .iter()
+                .map(|a| format!("{}{:?}", a.topic, a.partitions))
+                .collect(),
+        })
+        .collect();
+    Ok(GroupDescription {
+        name: group.name,
+        state: group.state,
+        protocol_type: group.protocol_type,
+        protocol: group.protocol,
+        members,
+        lag: offsets.iter().filter_map(|o| o.lag).sum(),
+        offsets,
+    })
+}
+
+impl Report for GroupDescription {
+    fn to_table(&self) -> String {
+        let mut text = format!(
+            "Name: {}\nState: {}\nProtocol: {} / {}\nLag: {}\n\n",
+            self.name, self.state, self.protocol_type, self.protocol, self.lag
+        );
+        let members: Vec<Vec<String>> = self
+            .members
+            .iter()
+            .map(|m| {
+                vec![
+                    m.id.to_owned(),
+                    m.client_id.to_owned(),
+                    m.host.to_owned(),
+                    m.assignments.join(" "),
+                ]
+            })
+            .collect();
+        text.push_str(&format_table(
+            &["MEMBER", "CLIENT ID", "HOST", "ASSIGNMENTS"],
+            &members,
+        ));
+        text.push('\n');
+        let unknown = || String::from("-");
+        let offsets: Vec<Vec<String>> = self
+            .offsets
+            .iter()
+            .map(|o| {
+                vec![
+                    o.topic.to_owned(),
+                    o.partition.to_string(),
+                    o.committed.to_string(),
+                    o.high_watermark.map_or_else(unknown, |h| h.to_string()),
+                    o.lag.map_or_else(unknown, |l| l.to_string()),
+                ]
+            })
+            .collect();
+        text.push_str(&format_table(
+            &["TOPIC", "PARTITION", "COMMITTED", "HIGH", "LAG"],
+            &offsets,
+        ));
+        text
+    }
+}
+
+#[derive(Serialize)]
+struct BrokerSummary {
+    id: i32,
+    host: String,
+    port: i32,
+    leader_partitions: usize,
+    preferred_leader_partitions: usize,
+}
+
+fn list_brokers(client: &dyn ClusterClient) -> KafkaResult<Vec<BrokerSummary>> {
+    let brokers = client.get_brokers()?;
+    let ids: Vec<i32> = brokers.iter().map(|b| b.id).collect();
+    let health = ClusterHealth::compute(&ids, &client.get_partition_infos()?, &[], vec![]);
+    let mut brokers: Vec<BrokerSummary> = brokers
+        .into_iter()
+        .map(|b| {
+            let leadership = health.leadership.iter().find(|l| l.broker_id == b.id);
+            BrokerSummary {
+                id: b.id,
+                host: b.host,
+                port: b.port,
+                leader_partitions: leadership.map_or(0, |l| l.leader_count),
+                preferred_leader_partitions: leadership.map_or(0, |l| l.preferred_count),
+            }
+        })
+        .collect();
+    brokers.sort_by_key(|b| b.id);
+    Ok(brokers)
+}
+
+impl Report for Vec<BrokerSummary> {
+    fn to_table(&self) -> String {
+        let rows: Vec<Vec<String>> = self
+            .iter()
+            .map(|b| {
+                vec![
+                    b.id.to_string(),
+                    b.host.to_owned(),
+                    b.port.to_string(),
+                    b.leader_partitions.to_string(),
+                    b.preferred_leader_partitions.to_string(),
+                ]
+            })
+            .collect();
+        format_table(
+            &[
+                "ID",
+                "HOST",
+                "PORT",
+                "LEADER PARTITIONS",
+                "PREFERRED LEADER",
+            ],
+            &rows,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_cluster::FakeCluster;
+
+    fn cluster() -> FakeCluster {
+        let mut cluster = FakeCluster::sample();
+        cluster.produce("orders", 0, 100);
+        cluster.produce("orders", 1, 50);
+        cluster.commit("billing", "orders", 0, 80);
+        cluster.commit("billing", "orders", 1, 50);
+        cluster
+    }
+
+    fn run_command(cluster: &FakeCluster, command: &[&str]) -> Result<String, String> {
+        let args = crate::args()
+            .get_matches_from_safe(std::iter::once("kafui").chain(command.iter().copied()))
+            .unwrap();
+        run(cluster, &args).map(|output| output.text)
+    }
+
+    #[test]
+    fn lists_topics_as_a_table() {
+        let output = run_command(&cluster(), &["topics", "list"]).unwrap();
+        assert_eq!(
+            output,
+            "NAME      PARTITIONS  RF  MESSAGES  CLEANUP  GROUPS  UNDER-REPLICATED\n\
+             orders    2           2   150       delete   1       0\n\
+             payments  1           2   0         delete   0       0\n"
+        );
+    }
+
+    #[test]
+    fn describes_a_topic_in_json() {
+        let output = run_command(&cluster(), &["topic", "describe", "orders", "-o", "json"]);
+        let json: serde_json::Value = serde_json::from_str(&output.unwrap()).unwrap();
+        assert_eq!(json["name"], "orders");
+        assert_eq!(json["messages"], 150);
+        assert_eq!(json["consumer_groups"], serde_json::json!(["billing"]));
+        assert_eq!(json["partitions"][1]["leader"], 2);
+        assert_eq!(json["partitions"][1]["replicas"], serde_json::json!([2, 1]));
+        assert_eq!(json["partitions"][1]["high"], 50);
+    }
+
+    #[test]
+    fn describes_the_lag_of_a_group_in_yaml() {
+        let output = run_command(
+            &cluster(),
+            &["--output", "yaml", "group", "describe", "billing"],
+        );
+        let yaml: serde_yaml::Value = serde_yaml::from_str(&output.unwrap()).unwrap();
+        assert_eq!(yaml["lag"], serde_yaml::Value::from(20));
+        assert_eq!(yaml["state"], serde_yaml::Value::from("Stable"));
+        assert_eq!(yaml["offsets"][0]["lag"], serde_yaml::Value::from(20));
+        assert_eq!(yaml["offsets"][1]["lag"], serde_yaml::Value::from(0));
+        assert_eq!(
+            yaml["members"][0]["assignments"][0],
+            serde_yaml::Value::from("orders[0, 1]")
+        );
+    }
+
+    #[test]
+    fn lists_brokers_with_their_leadership() {
+        let output = run_command(&cluster(), &["brokers"]).unwrap();
+        assert_eq!(
+            output,
+            "ID  HOST      PORT  LEADER PARTITIONS  PREFERRED LEADER\n\
+             1   broker-1  9092  2                  2\n\
+             2   broker-2  9092  1                  1\n"
+        );
+    }
+
+    #[test]
+    fn reports_unknown_topics_and_groups() {
+        let cluster = cluster();
+        assert_eq!(
+            run_command(&cluster, &["topic", "describe", "unknown"]),
+            Err(String::from("No topic unknown"))
+        );
+        assert_eq!(
+            run_command(&cluster, &["group", "describe", "unknown"]),
+            Err(String::from("No group unknown"))
+        );
+    }
+
+    #[test]
+    fn reports_an_unreachable_cluster() {
+        let mut cluster = cluster();
+        cluster.unreachable = true;
+        for command in &[
+            &["topics", "list"][..],
+            &["topic", "describe", "orders"],
+            &["groups", "list"],
+            &["group", "describe", "billing"],
+            &["brokers"],
+        ] {
+            let error = run_command(&cluster, command).unwrap_err();
+            assert!(
+                error.contains("All broker connections are down"),
+                "{}",
+                error
+            );
+        }
+        let args = crate::args()
+            .get_matches_from_safe(vec![
+                "kafui",
+                "lag",
+                "check",
+                "--group",
+                "billing",
+                "--max-lag",
+                "0",
+            ])
+            .unwrap();
+        let output = run(&cluster, &args).unwrap();
+        assert_eq!(output.exit_code, crate::lag_check::UNKNOWN_EXIT_CODE);
+        assert!(output.text.starts_with("UNKNOWN: "), "{}", output.text);
+    }
+}
@@ -0,0 +1,57 @@
+use crate::model::{
+    BrokerInfo, ClusterOverview, GroupInfo, PartitionInfo, TopicConfig, TopicDetail, TopicInfo,
+};
+use rdkafka::error::KafkaResult;
+use std::collections::HashMap;
+
+/// The requests made to a cluster, implemented with rdkafka by `KafkaWrapper` and in memory by
+/// the fake cluster of the tests. A failed request is an error rather than empty data, which
+/// would read as an empty cluster.
+pub trait ClusterClient: Send {
+    /// Topics with their watermarks and configuration, `groups` being used to count the
+    /// consumers of each topic.
+    fn get_topic_infos(&self, groups: &[GroupInfo]) -> KafkaResult<Vec<TopicInfo>>;
+
+    fn get_brokers(&self) -> KafkaResult<Vec<BrokerInfo>>;
+
+    fn get_partition_infos(&self) -> KafkaResult<Vec<PartitionInfo>>;
+
+    /// The cluster infos, the topics, the brokers and the partitions read from the same
+    /// metadata, fetched once rather than by each of the requests above.
+    fn get_overview(&self, groups: &[GroupInfo]) -> KafkaResult<ClusterOverview>;
+
+    fn get_topic_configs(&self, topics: &[String]) -> KafkaResult<HashMap<String, TopicConfig>>;
+
+    /// High watermarks of the given (topic, partition), missing when unknown.
+    fn get_high_watermarks(
+        &self,
+        partitions: &[(String, i32)],
+    ) -> KafkaResult<HashMap<(String, i32), i64>>;
+
+    /// (low, high) watermarks of the given (topic, partition), missing when unknown.
+    fn get_watermarks(
+        &self,
+        partitions: &[(String, i32)],
+    ) -> KafkaResult<HashMap<(String, i32), (i64, i64)>>;
+
+    /// Groups sorted by name, with their members.
+    fn get_group_infos(&self) -> KafkaResult<Vec<GroupInfo>>;
+
+    /// The detail of a topic, `None` when it does not exist.
+    fn get_topic_detail(
+        &self,
+        topic_name: &str,
+        groups: &[GroupInfo],
+    ) -> KafkaResult<Option<TopicDetail>>;
+
+    /// Offsets committed by a group on the partitions of the non internal topics, asked to its
+    /// coordinator.
+    fn get_committed_offsets(&self, group: &str) -> KafkaResult<HashMap<(String, i32), i64>>;
+
+    /// Timestamps in milliseconds of the first messages available from the given offsets of
+    /// (topic, partition), missing when the partition has no message there.
+    fn get_message_timestamps(
+        &self,
+        offsets: &[((String, i32), i64)],
+    ) -> KafkaResult<HashMap<(String, i32), i64>>;
+}
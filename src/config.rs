@@ -1,22 +1,341 @@
+use crate::app::Context;
+use crate::keymap::Action;
 use clap::ArgMatches;
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
+use std::io::ErrorKind;
+use std::time::Duration;
+
+/// Where the committed offsets of the consumer groups are read from.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum OffsetsSourceKind {
+    /// Replay the `__consumer_offsets` internal topic, which needs read access to it.
+    #[default]
+    ConsumerOffsets,
+    /// Ask the group coordinators for the committed offsets of each group, which only needs
+    /// describe access to the groups.
+    Coordinator,
+}
+
+/// Refresh intervals of the pages, in milliseconds.
+#[derive(Debug, Deserialize, Clone)]
+pub struct RefreshConfig {
+    #[serde(default = "default_interval")]
+    pub interval_ms: u64,
+    /// Intervals of some pages, overriding `interval_ms`.
+    pub topic_list_ms: Option<u64>,
+    pub topic_detail_ms: Option<u64>,
+    pub group_list_ms: Option<u64>,
+    pub group_detail_ms: Option<u64>,
+}
+
+fn default_interval() -> u64 {
+    5000
+}
+
+impl Default for RefreshConfig {
+    fn default() -> Self {
+        RefreshConfig {
+            interval_ms: default_interval(),
+            topic_list_ms: None,
+            topic_detail_ms: None,
+            group_list_ms: None,
+            group_detail_ms: None,
+        }
+    }
+}
+
+impl RefreshConfig {
+    pub fn interval(&self, context: &Context) -> Duration {
+        let page = match context {
+            Context::TopicListPage => self.topic_list_ms,
+            Context::TopicDetailPage => self.topic_detail_ms,
+            Context::GroupListPage => self.group_list_ms,
+            Context::GroupDetailPage => self.group_detail_ms,
+        };
+        Duration::from_millis(page.unwrap_or(self.interval_ms))
+    }
+
+    /// The interval of the page refreshed the most often.
+    pub fn shortest_interval(&self) -> Duration {
+        [
+            Context::TopicListPage,
+            Context::TopicDetailPage,
+            Context::GroupListPage,
+            Context::GroupDetailPage,
+        ]
+        .iter()
+        .map(|context| self.interval(context))
+        .min()
+        .unwrap()
+    }
+}
+
+/// Alerting rules, evaluated each tick. The firing alerts are shown in a banner of the UI, and
+/// posted to the webhook by the exporter.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AlertsConfig {
+    /// URL receiving a JSON POST when alerts start or stop firing.
+    pub webhook: Option<String>,
+    #[serde(default)]
+    pub rules: Vec<AlertRule>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+    /// How long the condition must hold before the alert fires.
+    #[serde(default)]
+    pub for_ms: u64,
+}
+
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// The lag of a group, on a topic or over all of them, is above a number of messages.
+    GroupLag {
+        group: String,
+        topic: Option<String>,
+        max_lag: i64,
+    },
+    /// Partitions of a topic, or of any topic, have fewer in-sync replicas than replicas.
+    UnderReplicatedPartitions { topic: Option<String> },
+    /// A group, or any group, is in one of the states.
+    GroupState {
+        group: Option<String>,
+        #[serde(default = "default_alert_states")]
+        states: Vec<String>,
+    },
+}
+
+fn default_alert_states() -> Vec<String> {
+    vec![String::from("Empty"), String::from("Dead")]
+}
+
+/// Keys of the actions of each section, replacing their default keys, e.g.
+/// `"topic_list": {"down": ["j", "down"]}`. The global actions are the ones of every page.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct KeysConfig {
+    #[serde(default)]
+    pub global: HashMap<Action, Vec<String>>,
+    #[serde(default)]
+    pub topic_list: HashMap<Action, Vec<String>>,
+    #[serde(default)]
+    pub topic_detail: HashMap<Action, Vec<String>>,
+    #[serde(default)]
+    pub group_list: HashMap<Action, Vec<String>>,
+    #[serde(default)]
+    pub group_detail: HashMap<Action, Vec<String>>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeName {
+    #[default]
+    Dark,
+    Light,
+    /// No colors, only bold, reversed and underlined text. Forced by the `NO_COLOR` environment
+    /// variable.
+    NoColor,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ThemeColor {
+    Black,
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Magenta,
+    Cyan,
+    Gray,
+    DarkGray,
+    LightRed,
+    LightGreen,
+    LightYellow,
+    LightBlue,
+    LightMagenta,
+    LightCyan,
+    White,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ThemeConfig {
+    #[serde(default)]
+    pub name: ThemeName,
+    /// Color of the borders, e.g. red to tell a production cluster apart.
+    pub border: Option<ThemeColor>,
+}
+
+/// Settings of a cluster, selected with `--profile`, which replace the top level ones.
+#[derive(Debug, Deserialize)]
+pub struct Profile {
+    pub brokers: String,
+    pub refresh: Option<RefreshConfig>,
+    pub alerts: Option<AlertsConfig>,
+    pub theme: Option<ThemeConfig>,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct Config {
+    #[serde(default)]
     pub brokers: String,
+    #[serde(default)]
+    pub offsets_source: OffsetsSourceKind,
+    /// `client.id` of the clients reading the committed offsets, unique per process by default.
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+    #[serde(default)]
+    pub refresh: RefreshConfig,
+    #[serde(default)]
+    pub alerts: AlertsConfig,
+    #[serde(default)]
+    pub keys: KeysConfig,
+    #[serde(default)]
+    pub theme: ThemeConfig,
+    #[serde(default)]
+    pub profiles: HashMap<String, Profile>,
+}
+
+fn default_client_id() -> String {
+    format!("kafui-{}", std::process::id())
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            brokers: String::new(),
+            offsets_source: OffsetsSourceKind::default(),
+            client_id: default_client_id(),
+            refresh: RefreshConfig::default(),
+            alerts: AlertsConfig::default(),
+            keys: KeysConfig::default(),
+            theme: ThemeConfig::default(),
+            profiles: HashMap::new(),
+        }
+    }
 }
 
 impl Config {
-    pub fn new(args: &ArgMatches) -> Config {
-        let file_path = args.value_of("config-file").unwrap();
-        let content = fs::read_to_string(file_path).expect("Something went wrong reading the file");
-        let config_from_file: Result<Config, _> = serde_json::from_str(&content);
-
-        let brokers = args
-            .value_of("brokers")
-            .map(|v| v.to_string())
-            .unwrap_or_else(|| config_from_file.expect("Failed to find conf file").brokers);
-        Config { brokers }
+    pub fn new(args: &ArgMatches) -> Result<Config, String> {
+        Config::load(
+            args.value_of("config-file").unwrap(),
+            args.value_of("profile"),
+            args.value_of("brokers"),
+        )
+    }
+
+    /// The config of the file, which may only be missing when the brokers are given on the
+    /// command line. A file which can't be read or parsed is an error whatever the arguments.
+    fn load(
+        file_path: &str,
+        profile: Option<&str>,
+        brokers: Option<&str>,
+    ) -> Result<Config, String> {
+        let mut config = match fs::read_to_string(file_path) {
+            Ok(content) => serde_json::from_str::<Config>(&content)
+                .map_err(|e| format!("Failed to parse the conf file {}: {}", file_path, e))?,
+            Err(e) if e.kind() == ErrorKind::NotFound && brokers.is_some() => Config::default(),
+            Err(e) => return Err(format!("Failed to read the conf file {}: {}", file_path, e)),
+        };
+        if let Some(name) = profile {
+            let profile = config
+                .profiles
+                .remove(name)
+                .ok_or_else(|| format!("No profile {} in {}", name, file_path))?;
+            config.brokers = profile.brokers;
+            if let Some(refresh) = profile.refresh {
+                config.refresh = refresh;
+            }
+            if let Some(alerts) = profile.alerts {
+                config.alerts = alerts;
+            }
+            if let Some(theme) = profile.theme {
+                config.theme = theme;
+            }
+        }
+        if let Some(brokers) = brokers {
+            config.brokers = brokers.to_string();
+        }
+        Ok(config)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// A config file unique to the test, removed when dropped.
+    struct ConfigFile(PathBuf);
+
+    impl ConfigFile {
+        fn new(name: &str, content: &str) -> ConfigFile {
+            let path = std::env::temp_dir().join(format!("kafui-{}-{}.json", name, process::id()));
+            fs::write(&path, content).unwrap();
+            ConfigFile(path)
+        }
+
+        fn path(&self) -> &str {
+            self.0.to_str().unwrap()
+        }
+    }
+
+    impl Drop for ConfigFile {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    #[test]
+    fn reads_the_profile_of_the_file() {
+        let file = ConfigFile::new(
+            "profile",
+            r#"{"brokers": "localhost:9092", "profiles": {"prod": {"brokers": "prod:9092"}}}"#,
+        );
+        let load = |profile| Config::load(file.path(), profile, None);
+        assert_eq!(load(None).unwrap().brokers, "localhost:9092");
+        assert_eq!(load(Some("prod")).unwrap().brokers, "prod:9092");
+        assert_eq!(
+            load(Some("staging")).err().unwrap(),
+            format!("No profile staging in {}", file.path())
+        );
+    }
+
+    #[test]
+    fn finds_the_shortest_refresh_interval() {
+        let mut refresh = RefreshConfig::default();
+        assert_eq!(refresh.shortest_interval(), Duration::from_secs(5));
+        refresh.group_detail_ms = Some(1000);
+        refresh.topic_list_ms = Some(10000);
+        assert_eq!(refresh.shortest_interval(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn needs_a_file_only_without_brokers() {
+        let path = "/nonexistent/kafui.json";
+        let config = Config::load(path, None, Some("localhost:9092")).unwrap();
+        assert_eq!(config.brokers, "localhost:9092");
+        let error = Config::load(path, None, None).err().unwrap();
+        assert!(error.starts_with("Failed to read the conf file /nonexistent/kafui.json"));
+    }
+
+    #[test]
+    fn reports_a_file_which_does_not_parse() {
+        let file = ConfigFile::new("invalid", r#"{"brokers": 9092}"#);
+        let error = Config::load(file.path(), None, Some("localhost:9092"))
+            .err()
+            .unwrap();
+        assert!(
+            error.starts_with("Failed to parse the conf file"),
+            "{}",
+            error
+        );
     }
 }
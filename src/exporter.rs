@@ -0,0 +1,519 @@
+use crate::alerts::{topic_lags, Alerts, ClusterState, Webhook};
+use crate::app::Context;
+use crate::cluster::ClusterClient;
+use crate::config::Config;
+use crate::kafka::KafkaWrapper;
+use crate::model::OffsetsStore;
+use crate::offsets_source::build_offsets_source;
+use crate::refresher::{fetch_topic_list, TopicListSnapshot};
+use crate::utils::now_ms;
+use clap::{App as ClapApp, Arg, SubCommand};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{info, warn};
+use rdkafka::error::KafkaResult;
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::error::Error;
+use std::fmt::{Display, Write};
+use std::net::{SocketAddr, TcpListener};
+use std::sync::{Arc, Mutex};
+use tokio::task;
+
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+pub fn subcommand() -> ClapApp<'static, 'static> {
+    SubCommand::with_name("exporter")
+        .about("Serve the metrics of the cluster to Prometheus instead of starting the UI")
+        .arg(
+            Arg::with_name("listen")
+                .long("listen")
+                .help("address to serve the metrics on, at /metrics")
+                .takes_value(true)
+                .default_value("0.0.0.0:9308")
+                .validator(|v| {
+                    v.parse::<SocketAddr>()
+                        .map(|_| ())
+                        .map_err(|e| e.to_string())
+                }),
+        )
+}
+
+/// Refresh the metrics at the interval of the topic list, as the UI would, and serve the last
+/// ones, or only `kafui_up 0` while the cluster can't be reached. The alert rules are evaluated
+/// at each successful refresh, their changes posted to the webhook, and posted again at the next
+/// refreshes when the webhook can't be reached.
+#[tokio::main]
+pub async fn run(config: Config, listen: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let client = KafkaWrapper::new(&config.brokers)?;
+    let offsets = Arc::new(Mutex::new(OffsetsStore::default()));
+    tokio::spawn(build_offsets_source(&config, Arc::clone(&offsets))?.run());
+
+    let metrics = Arc::new(Mutex::new(String::new()));
+    let refreshed = Arc::clone(&metrics);
+    let interval = config.refresh.interval(&Context::TopicListPage);
+    let mut alerts = Alerts::new(config.alerts.rules.clone());
+    let mut webhook = config.alerts.webhook.as_deref().map(Webhook::new);
+    tokio::spawn(async move {
+        loop {
+            let notifications = match task::block_in_place(|| collect(&client, &offsets)) {
+                Ok(collected) => {
+                    let now = now_ms();
+                    *refreshed.lock().unwrap() = render(&collected, now);
+                    let lags = collected.committed.as_ref().map(|committed| {
+                        topic_lags(committed, &collected.snapshot.high_watermarks)
+                    });
+                    let state = ClusterState {
+                        groups: &collected.snapshot.group_infos,
+                        topics: &collected.snapshot.topic_infos,
+                        lags: lags.as_ref(),
+                    };
+                    alerts.evaluate(&state, now)
+                }
+                // The alerts are left as they are until the cluster can be reached again.
+                Err(e) => {
+                    warn!("Failed to refresh the metrics: {}", e);
+                    *refreshed.lock().unwrap() = render_down();
+                    vec![]
+                }
+            };
+            if let Some(webhook) = &mut webhook {
+                if let Err(e) = webhook.send(notifications).await {
+                    warn!("Failed to post the alerts to the webhook: {}", e);
+                }
+            }
+            tokio::time::delay_for(interval).await;
+        }
+    });
+
+    info!("Serving the metrics on http://{}/metrics", listen);
+    serve(TcpListener::bind(listen)?, metrics).await?;
+    Ok(())
+}
+
+/// Serve the metrics until the process exits.
+async fn serve(listener: TcpListener, metrics: Arc<Mutex<String>>) -> hyper::Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let metrics = Arc::clone(&metrics);
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let response = respond(&request, &metrics.lock().unwrap());
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    Server::from_tcp(listener)?.serve(make_service).await
+}
+
+fn respond(request: &Request<Body>, metrics: &str) -> Response<Body> {
+    let (status, content_type, body) = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => (StatusCode::OK, CONTENT_TYPE, metrics.to_owned()),
+        (&Method::GET, "/") => (
+            StatusCode::OK,
+            "text/html",
+            String::from("<html><body><a href=\"/metrics\">Metrics</a></body></html>"),
+        ),
+        _ => (
+            StatusCode::NOT_FOUND,
+            "text/plain",
+            String::from("Not found"),
+        ),
+    };
+    Response::builder()
+        .status(status)
+        .header("Content-Type", content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Data of a refresh: the topic list, and the watermarks of every partition.
+struct Collected {
+    snapshot: TopicListSnapshot,
+    watermarks: HashMap<(String, i32), (i64, i64)>,
+    /// Committed offsets sorted by group, topic and partition, None until they are loaded.
+    committed: Option<Vec<(String, String, i32, i64)>>,
+}
+
+fn collect(client: &dyn ClusterClient, offsets: &Mutex<OffsetsStore>) -> KafkaResult<Collected> {
+    let snapshot = fetch_topic_list(client, offsets)?;
+    let partitions: Vec<(String, i32)> = snapshot
+        .partitions
+        .iter()
+        .map(|p| (p.topic.to_owned(), p.id))
+        .collect();
+    let watermarks = client.get_watermarks(&partitions)?;
+    let offsets = offsets.lock().unwrap();
+    // The offsets of a partial replay are outdated, and so would be the lag.
+    let committed = if offsets.replay.is_complete() {
+        let mut committed = offsets.committed_offsets();
+        committed.sort();
+        Some(committed)
+    } else {
+        None
+    };
+    Ok(Collected {
+        snapshot,
+        watermarks,
+        committed,
+    })
+}
+
+/// A metric family of the text format, whose samples must follow each other.
+struct Family<'a> {
+    out: &'a mut String,
+    name: &'static str,
+}
+
+impl<'a> Family<'a> {
+    fn gauge(out: &'a mut String, name: &'static str, help: &str) -> Family<'a> {
+        writeln!(out, "# HELP {} {}", name, help).unwrap();
+        writeln!(out, "# TYPE {} gauge", name).unwrap();
+        Family { out, name }
+    }
+
+    fn sample<V: Display>(&mut self, labels: &[(&str, &str)], value: V) {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+            .collect();
+        if labels.is_empty() {
+            writeln!(self.out, "{} {}", self.name, value).unwrap();
+        } else {
+            writeln!(self.out, "{}{{{}}} {}", self.name, labels.join(","), value).unwrap();
+        }
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+const UP_HELP: &str = "1 when the last refresh of the metrics succeeded.";
+
+/// The metrics of a failed refresh: the previous values are dropped rather than served as if
+/// they were current.
+fn render_down() -> String {
+    let mut out = String::new();
+    Family::gauge(&mut out, "kafui_up", UP_HELP).sample(&[], 0);
+    out
+}
+
+/// The metrics in the Prometheus text format, sorted by topic, group and partition so that
+/// successive scrapes read alike.
+fn render(collected: &Collected, now_ms: i64) -> String {
+    let Collected {
+        snapshot,
+        watermarks,
+        committed,
+    } = collected;
+    let committed = committed.as_deref().unwrap_or_default();
+    let mut out = String::new();
+    Family::gauge(&mut out, "kafui_up", UP_HELP).sample(&[], 1);
+    Family::gauge(
+        &mut out,
+        "kafui_last_refresh_timestamp_seconds",
+        "Time of the last refresh of the metrics.",
+    )
+    .sample(&[], now_ms as f64 / 1000.0);
+    Family::gauge(
+        &mut out,
+        "kafui_brokers",
+        "Number of brokers of the cluster.",
+    )
+    .sample(&[], snapshot.brokers.len());
+
+    let mut topics: Vec<_> = snapshot.topic_infos.iter().collect();
+    topics.sort_by(|a, b| a.name.cmp(&b.name));
+    let mut family = Family::gauge(&mut out, "kafui_topic_partitions", "Number of partitions.");
+    for topic in &topics {
+        family.sample(&[("topic", &topic.name)], topic.nb_partitions);
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_topic_messages",
+        "Number of messages in the topic, between the low and high watermarks.",
+    );
+    for topic in &topics {
+        family.sample(&[("topic", &topic.name)], topic.message_count);
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_topic_under_replicated_partitions",
+        "Number of partitions with fewer in-sync replicas than replicas.",
+    );
+    for topic in &topics {
+        family.sample(&[("topic", &topic.name)], topic.under_replicated);
+    }
+
+    let mut partitions: Vec<_> = snapshot.partitions.iter().collect();
+    partitions.sort_by(|a, b| (&a.topic, a.id).cmp(&(&b.topic, b.id)));
+    let partition_watermarks: Vec<_> = partitions
+        .iter()
+        .filter_map(|p| {
+            let id = p.id.to_string();
+            watermarks
+                .get(&(p.topic.to_owned(), p.id))
+                .map(|&(low, high)| (p.topic.as_str(), id, low, high))
+        })
+        .collect();
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_partition_low_watermark",
+        "Offset of the first message of the partition.",
+    );
+    for (topic, id, low, _) in &partition_watermarks {
+        family.sample(&[("topic", topic), ("partition", id.as_str())], low);
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_partition_high_watermark",
+        "Offset of the next message produced to the partition.",
+    );
+    for (topic, id, _, high) in &partition_watermarks {
+        family.sample(&[("topic", topic), ("partition", id.as_str())], high);
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_partition_leader",
+        "Broker leading the partition, -1 when offline.",
+    );
+    for p in &partitions {
+        family.sample(
+            &[("topic", &p.topic), ("partition", &p.id.to_string())],
+            p.leader,
+        );
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_partition_in_sync_replicas",
+        "Number of in-sync replicas of the partition.",
+    );
+    for p in &partitions {
+        let labels = [
+            ("topic", p.topic.as_str()),
+            ("partition", &p.id.to_string()),
+        ];
+        family.sample(&labels, p.isr.len());
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_partition_under_replicated",
+        "1 when the partition has fewer in-sync replicas than replicas.",
+    );
+    for p in &partitions {
+        let labels = [
+            ("topic", p.topic.as_str()),
+            ("partition", &p.id.to_string()),
+        ];
+        family.sample(&labels, p.is_under_replicated() as u8);
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_partition_offline",
+        "1 when the partition has no leader.",
+    );
+    for p in &partitions {
+        let labels = [
+            ("topic", p.topic.as_str()),
+            ("partition", &p.id.to_string()),
+        ];
+        family.sample(&labels, p.is_offline() as u8);
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_partition_preferred_leader",
+        "1 when the partition is led by its preferred leader.",
+    );
+    for p in &partitions {
+        let labels = [
+            ("topic", p.topic.as_str()),
+            ("partition", &p.id.to_string()),
+        ];
+        family.sample(&labels, (p.preferred_leader() == Some(p.leader)) as u8);
+    }
+
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_group_members",
+        "Number of members of the consumer group, labelled with its state.",
+    );
+    for group in &snapshot.group_infos {
+        let labels = [("group", group.name.as_str()), ("state", &group.state)];
+        family.sample(&labels, group.members.len());
+    }
+
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_group_committed_offset",
+        "Offset committed by the consumer group.",
+    );
+    for (group, topic, partition, offset) in committed {
+        let labels = [
+            ("group", group.as_str()),
+            ("topic", topic),
+            ("partition", &partition.to_string()),
+        ];
+        family.sample(&labels, offset);
+    }
+    let mut family = Family::gauge(
+        &mut out,
+        "kafui_group_lag",
+        "Number of messages left to consume by the consumer group.",
+    );
+    for (group, topic, partition, offset) in committed {
+        if let Some(high) = snapshot
+            .high_watermarks
+            .get(&(topic.to_owned(), *partition))
+        {
+            let labels = [
+                ("group", group.as_str()),
+                ("topic", topic),
+                ("partition", &partition.to_string()),
+            ];
+            family.sample(&labels, (high - offset).max(0));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_cluster::FakeCluster;
+    use std::io::{Read, Write as IoWrite};
+    use std::net::TcpStream;
+
+    fn cluster() -> FakeCluster {
+        let mut cluster = FakeCluster::sample();
+        cluster.produce("orders", 0, 100);
+        cluster.partition_mut("orders", 0).low = 40;
+        cluster.produce("orders", 1, 10);
+        cluster.partition_mut("orders", 1).isr = vec![2];
+        cluster
+    }
+
+    fn offsets(committed: &[(&str, &str, i32, i64)]) -> Mutex<OffsetsStore> {
+        Mutex::new(OffsetsStore::with_committed(committed))
+    }
+
+    fn rendered(cluster: &FakeCluster, offsets: &Mutex<OffsetsStore>) -> String {
+        render(&collect(cluster, offsets).unwrap(), 0)
+    }
+
+    fn samples(metrics: &str, name: &str) -> Vec<String> {
+        metrics
+            .lines()
+            .filter(|l| l.starts_with(name) && l[name.len()..].starts_with(['{', ' ']))
+            .map(str::to_owned)
+            .collect()
+    }
+
+    #[test]
+    fn exports_topics_and_partitions() {
+        let metrics = rendered(&cluster(), &offsets(&[]));
+        assert!(metrics.contains(
+            "# HELP kafui_topic_messages Number of messages in the topic, between the low and \
+             high watermarks.\n# TYPE kafui_topic_messages gauge\n\
+             kafui_topic_messages{topic=\"orders\"} 70\n"
+        ));
+        assert_eq!(samples(&metrics, "kafui_up"), vec!["kafui_up 1"]);
+        assert_eq!(samples(&metrics, "kafui_brokers"), vec!["kafui_brokers 2"]);
+        assert_eq!(
+            samples(&metrics, "kafui_partition_low_watermark"),
+            vec![
+                "kafui_partition_low_watermark{topic=\"orders\",partition=\"0\"} 40",
+                "kafui_partition_low_watermark{topic=\"orders\",partition=\"1\"} 0",
+                "kafui_partition_low_watermark{topic=\"payments\",partition=\"0\"} 0",
+            ]
+        );
+        assert_eq!(
+            samples(&metrics, "kafui_partition_under_replicated"),
+            vec![
+                "kafui_partition_under_replicated{topic=\"orders\",partition=\"0\"} 0",
+                "kafui_partition_under_replicated{topic=\"orders\",partition=\"1\"} 1",
+                "kafui_partition_under_replicated{topic=\"payments\",partition=\"0\"} 0",
+            ]
+        );
+        assert_eq!(
+            samples(&metrics, "kafui_topic_under_replicated_partitions"),
+            vec![
+                "kafui_topic_under_replicated_partitions{topic=\"orders\"} 1",
+                "kafui_topic_under_replicated_partitions{topic=\"payments\"} 0",
+            ]
+        );
+        assert_eq!(
+            samples(&metrics, "kafui_group_members"),
+            vec!["kafui_group_members{group=\"billing\",state=\"Stable\"} 1"]
+        );
+    }
+
+    #[test]
+    fn exports_the_lag_per_partition() {
+        let offsets = offsets(&[("billing", "orders", 0, 90), ("billing", "orders", 1, 10)]);
+        let metrics = rendered(&cluster(), &offsets);
+        assert_eq!(
+            samples(&metrics, "kafui_group_lag"),
+            vec![
+                "kafui_group_lag{group=\"billing\",topic=\"orders\",partition=\"0\"} 10",
+                "kafui_group_lag{group=\"billing\",topic=\"orders\",partition=\"1\"} 0",
+            ]
+        );
+
+        // Until the offsets are loaded, the lag is unknown.
+        offsets.lock().unwrap().replay = Default::default();
+        let metrics = rendered(&cluster(), &offsets);
+        assert!(samples(&metrics, "kafui_group_lag").is_empty());
+        assert!(metrics.contains("# TYPE kafui_group_lag gauge\n"));
+    }
+
+    #[test]
+    fn exports_only_the_failure_of_a_refresh() {
+        let mut cluster = cluster();
+        cluster.unreachable = true;
+        assert!(collect(&cluster, &offsets(&[])).is_err());
+        assert_eq!(
+            render_down(),
+            "# HELP kafui_up 1 when the last refresh of the metrics succeeded.\n\
+             # TYPE kafui_up gauge\nkafui_up 0\n"
+        );
+    }
+
+    #[test]
+    fn escapes_label_values() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: kafui\r\nConnection: close\r\n\r\n",
+            path
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[tokio::test(threaded_scheduler)]
+    async fn serves_the_metrics_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let metrics = Arc::new(Mutex::new(String::from("kafui_brokers 3\n")));
+        tokio::spawn(serve(listener, metrics));
+
+        let response = task::spawn_blocking(move || (get(addr, "/metrics"), get(addr, "/nothing")))
+            .await
+            .unwrap();
+        assert!(response.0.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response
+            .0
+            .contains("content-type: text/plain; version=0.0.4\r\n"));
+        assert!(response.0.ends_with("\r\n\r\nkafui_brokers 3\n"));
+        assert!(response.1.starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
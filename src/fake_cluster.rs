@@ -0,0 +1,381 @@
+use crate::cluster::ClusterClient;
+use crate::model::{
+    BrokerInfo, ClusterInfo, ClusterOverview, GroupInfo, GroupMember, MemberAssignment,
+    OffsetAndMetadata, OffsetValue, OffsetsStore, PartitionInfo, PartitionOffsets, TopicConfig,
+    TopicDetail, TopicInfo,
+};
+use crate::protocol::{ConsumerAssignment, MemberProtocol};
+use rdkafka::error::{KafkaError, KafkaResult, RDKafkaError};
+use std::collections::HashMap;
+
+/// In-memory cluster for the tests, answering the requests of the `App` without a broker.
+pub struct FakeCluster {
+    pub brokers: Vec<i32>,
+    pub topics: Vec<FakeTopic>,
+    pub groups: Vec<FakeGroup>,
+    /// When set, every request fails as if the brokers were down.
+    pub unreachable: bool,
+}
+
+pub struct FakeTopic {
+    pub name: String,
+    pub partitions: Vec<FakePartition>,
+}
+
+pub struct FakePartition {
+    pub leader: i32,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+    pub low: i64,
+    pub high: i64,
+    /// Timestamps of the messages produced with `produce_at`, by offset.
+    pub timestamps: HashMap<i64, i64>,
+}
+
+/// A consumer group of a single member, assigned the partitions of some topics.
+pub struct FakeGroup {
+    pub name: String,
+    pub assignments: Vec<(String, Vec<i32>)>,
+    pub committed: HashMap<(String, i32), i64>,
+}
+
+impl FakeCluster {
+    pub fn new(brokers: &[i32]) -> FakeCluster {
+        FakeCluster {
+            brokers: brokers.to_vec(),
+            topics: vec![],
+            groups: vec![],
+            unreachable: false,
+        }
+    }
+
+    /// The cluster the tests start from: `orders` of two partitions consumed by `billing`, and
+    /// `payments` of one partition, both empty and replicated on brokers 1 and 2.
+    pub fn sample() -> FakeCluster {
+        FakeCluster::new(&[1, 2])
+            .with_topic("orders", 2)
+            .with_topic("payments", 1)
+            .with_group("billing", &[("orders", &[0, 1])])
+    }
+
+    /// Add an empty topic, whose partitions are led by the brokers in turn and replicated on
+    /// all of them.
+    pub fn with_topic(mut self, name: &str, partitions: usize) -> FakeCluster {
+        let brokers = &self.brokers;
+        let partitions = (0..partitions)
+            .map(|p| {
+                let mut replicas = brokers.clone();
+                replicas.rotate_left(p % brokers.len());
+                FakePartition {
+                    leader: replicas[0],
+                    isr: replicas.clone(),
+                    replicas,
+                    low: 0,
+                    high: 0,
+                    timestamps: HashMap::new(),
+                }
+            })
+            .collect();
+        self.topics.push(FakeTopic {
+            name: name.to_owned(),
+            partitions,
+        });
+        self
+    }
+
+    pub fn with_group(mut self, name: &str, assignments: &[(&str, &[i32])]) -> FakeCluster {
+        self.groups.push(FakeGroup {
+            name: name.to_owned(),
+            assignments: assignments
+                .iter()
+                .map(|(topic, partitions)| (topic.to_string(), partitions.to_vec()))
+                .collect(),
+            committed: HashMap::new(),
+        });
+        self
+    }
+
+    pub fn commit(&mut self, group: &str, topic: &str, partition: i32, offset: i64) {
+        self.groups
+            .iter_mut()
+            .find(|g| g.name == group)
+            .expect("unknown group")
+            .committed
+            .insert((topic.to_owned(), partition), offset);
+    }
+
+    pub fn partition_mut(&mut self, topic: &str, partition: i32) -> &mut FakePartition {
+        self.topics
+            .iter_mut()
+            .find(|t| t.name == topic)
+            .and_then(|t| t.partitions.get_mut(partition as usize))
+            .expect("unknown partition")
+    }
+
+    pub fn produce(&mut self, topic: &str, partition: i32, count: i64) {
+        self.partition_mut(topic, partition).high += count;
+    }
+
+    pub fn produce_at(&mut self, topic: &str, partition: i32, timestamp: i64) {
+        let partition = self.partition_mut(topic, partition);
+        partition.timestamps.insert(partition.high, timestamp);
+        partition.high += 1;
+    }
+
+    fn reach(&self) -> KafkaResult<()> {
+        if self.unreachable {
+            return Err(KafkaError::MetadataFetch(RDKafkaError::AllBrokersDown));
+        }
+        Ok(())
+    }
+
+    fn topic_info(&self, topic: &FakeTopic, groups: &[GroupInfo]) -> TopicInfo {
+        let partitions = &topic.partitions;
+        TopicInfo {
+            name: topic.name.to_owned(),
+            nb_partitions: partitions.len(),
+            message_count: partitions.iter().map(|p| p.high - p.low).sum(),
+            high_watermark: partitions.iter().map(|p| p.high).sum(),
+            replication_factor: partitions
+                .iter()
+                .map(|p| p.replicas.len())
+                .max()
+                .unwrap_or(0),
+            config: TopicConfig::default(),
+            group_count: groups
+                .iter()
+                .filter(|g| g.consume_topic(&topic.name))
+                .count(),
+            under_replicated: partitions
+                .iter()
+                .filter(|p| p.isr.len() < p.replicas.len())
+                .count(),
+        }
+    }
+}
+
+impl OffsetsStore {
+    /// A store replayed to the end of `__consumer_offsets`, holding the offsets committed as
+    /// (group, topic, partition, offset).
+    pub fn with_committed(committed: &[(&str, &str, i32, i64)]) -> OffsetsStore {
+        let mut store = OffsetsStore::default();
+        for &(group, topic, partition, offset) in committed {
+            store.commit(group, topic, partition, offset);
+        }
+        store.replay.finish();
+        store
+    }
+
+    /// Record an offset as if its commit was replayed from `__consumer_offsets`.
+    pub fn commit(&mut self, group: &str, topic: &str, partition: i32, offset: i64) {
+        let key = OffsetAndMetadata::OffsetKey {
+            group: group.to_owned(),
+            topic: topic.to_owned(),
+            partition,
+        };
+        let value = OffsetValue {
+            offset,
+            leader_epoch: None,
+            metadata: String::new(),
+            commit_timestamp: None,
+            expire_timestamp: None,
+        };
+        self.offsets.insert(key, value);
+    }
+}
+
+impl ClusterClient for FakeCluster {
+    fn get_topic_infos(&self, groups: &[GroupInfo]) -> KafkaResult<Vec<TopicInfo>> {
+        self.reach()?;
+        Ok(self
+            .topics
+            .iter()
+            .map(|t| self.topic_info(t, groups))
+            .collect())
+    }
+
+    fn get_brokers(&self) -> KafkaResult<Vec<BrokerInfo>> {
+        self.reach()?;
+        Ok(self
+            .brokers
+            .iter()
+            .map(|&id| BrokerInfo {
+                id,
+                host: format!("broker-{}", id),
+                port: 9092,
+            })
+            .collect())
+    }
+
+    fn get_partition_infos(&self) -> KafkaResult<Vec<PartitionInfo>> {
+        self.reach()?;
+        Ok(self
+            .topics
+            .iter()
+            .flat_map(|t| {
+                t.partitions
+                    .iter()
+                    .enumerate()
+                    .map(move |(id, p)| PartitionInfo {
+                        topic: t.name.to_owned(),
+                        id: id as i32,
+                        leader: p.leader,
+                        replicas: p.replicas.clone(),
+                        isr: p.isr.clone(),
+                    })
+            })
+            .collect())
+    }
+
+    fn get_overview(&self, groups: &[GroupInfo]) -> KafkaResult<ClusterOverview> {
+        self.reach()?;
+        let cluster_info = ClusterInfo {
+            broker_count: self.brokers.len(),
+            topic_count: self.topics.len(),
+            broker_name: String::from("fake:9092"),
+            broker_id: self.brokers[0],
+        };
+        Ok(ClusterOverview {
+            cluster_info,
+            topic_infos: self.get_topic_infos(groups)?,
+            brokers: self.get_brokers()?,
+            partitions: self.get_partition_infos()?,
+        })
+    }
+
+    fn get_topic_configs(&self, topics: &[String]) -> KafkaResult<HashMap<String, TopicConfig>> {
+        self.reach()?;
+        Ok(topics
+            .iter()
+            .map(|t| (t.to_owned(), TopicConfig::default()))
+            .collect())
+    }
+
+    fn get_high_watermarks(
+        &self,
+        partitions: &[(String, i32)],
+    ) -> KafkaResult<HashMap<(String, i32), i64>> {
+        Ok(self
+            .get_watermarks(partitions)?
+            .into_iter()
+            .map(|(tp, (_, high))| (tp, high))
+            .collect())
+    }
+
+    fn get_watermarks(
+        &self,
+        partitions: &[(String, i32)],
+    ) -> KafkaResult<HashMap<(String, i32), (i64, i64)>> {
+        self.reach()?;
+        Ok(partitions
+            .iter()
+            .filter_map(|(topic, partition)| {
+                let p = self
+                    .topics
+                    .iter()
+                    .find(|t| &t.name == topic)?
+                    .partitions
+                    .get(*partition as usize)?;
+                Some(((topic.to_owned(), *partition), (p.low, p.high)))
+            })
+            .collect())
+    }
+
+    fn get_group_infos(&self) -> KafkaResult<Vec<GroupInfo>> {
+        self.reach()?;
+        let mut groups: Vec<GroupInfo> = self
+            .groups
+            .iter()
+            .map(|g| {
+                let assignments = g
+                    .assignments
+                    .iter()
+                    .map(|(topic, partitions)| MemberAssignment {
+                        topic: topic.to_owned(),
+                        partitions: partitions.clone(),
+                    })
+                    .collect();
+                GroupInfo {
+                    name: g.name.to_owned(),
+                    state: String::from("Stable"),
+                    protocol_type: String::from("consumer"),
+                    protocol: String::from("range"),
+                    members: vec![GroupMember {
+                        id: format!("{}-member", g.name),
+                        client_id: format!("{}-client", g.name),
+                        client_host: String::from("/127.0.0.1"),
+                        protocol: MemberProtocol::Consumer {
+                            subscription: None,
+                            assignment: Some(ConsumerAssignment {
+                                assignments,
+                                user_data: vec![],
+                            }),
+                            streams: None,
+                        },
+                    }],
+                }
+            })
+            .collect();
+        groups.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(groups)
+    }
+
+    fn get_topic_detail(
+        &self,
+        topic_name: &str,
+        groups: &[GroupInfo],
+    ) -> KafkaResult<Option<TopicDetail>> {
+        self.reach()?;
+        let topic = match self.topics.iter().find(|t| t.name == topic_name) {
+            Some(topic) => topic,
+            None => return Ok(None),
+        };
+        let offsets: Vec<PartitionOffsets> = topic
+            .partitions
+            .iter()
+            .enumerate()
+            .map(|(id, p)| PartitionOffsets {
+                low: p.low,
+                high: p.high,
+                count: p.high - p.low,
+                id: id as i32,
+                leader: p.leader,
+            })
+            .collect();
+        Ok(Some(TopicDetail {
+            info: self.topic_info(topic, groups),
+            message_count: offsets.iter().map(|p| p.count).sum(),
+            offsets,
+        }))
+    }
+
+    fn get_committed_offsets(&self, group: &str) -> KafkaResult<HashMap<(String, i32), i64>> {
+        self.reach()?;
+        Ok(self
+            .groups
+            .iter()
+            .find(|g| g.name == group)
+            .map(|g| g.committed.clone())
+            .unwrap_or_default())
+    }
+
+    fn get_message_timestamps(
+        &self,
+        offsets: &[((String, i32), i64)],
+    ) -> KafkaResult<HashMap<(String, i32), i64>> {
+        self.reach()?;
+        Ok(offsets
+            .iter()
+            .filter_map(|((topic, partition), offset)| {
+                let p = self
+                    .topics
+                    .iter()
+                    .find(|t| &t.name == topic)?
+                    .partitions
+                    .get(*partition as usize)?;
+                let timestamp = (*offset.max(&p.low)..p.high).find_map(|o| p.timestamps.get(&o))?;
+                Some(((topic.to_owned(), *partition), *timestamp))
+            })
+            .collect())
+    }
+}
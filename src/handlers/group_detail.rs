@@ -0,0 +1,9 @@
+use crate::app::App;
+use crate::app::Context::GroupListPage;
+use crate::keymap::Action;
+
+pub fn handle_action(action: Action, app: &mut App) {
+    if let Action::Back = action {
+        app.switch_context(GroupListPage)
+    }
+}
@@ -0,0 +1,14 @@
+use crate::app::App;
+use crate::keymap::Action;
+
+pub fn handle_action(action: Action, app: &mut App) {
+    match action {
+        Action::NextTab => app.next_tab(),
+        Action::Up => app.select_previous_group(),
+        Action::Down => app.select_next_group(),
+        Action::First => app.select_first_group(),
+        Action::Last => app.select_last_group(),
+        Action::Open => app.select_current_group(),
+        _ => {}
+    }
+}
@@ -1,16 +1,118 @@
+mod group_detail;
+mod group_list;
 mod topic_detail;
 mod topic_list;
 
 use super::app::App;
-use super::app::Context::{TopicDetailPage, TopicListPage};
-use crate::model::Event;
-use crossterm::event::KeyCode;
-use std::borrow::BorrowMut;
+use super::app::Context::{GroupDetailPage, GroupListPage, TopicDetailPage, TopicListPage};
+use crate::keymap::Action;
+use crossterm::event::{KeyCode, MouseButton, MouseEvent};
+use std::time::{Duration, Instant};
+use tui::layout::Rect;
 
-pub fn handle_event(event: Event<KeyCode>, app: &mut App) {
-    let context = app.context.borrow_mut();
-    match context {
-        TopicListPage => topic_list::handle_key(event, app),
-        TopicDetailPage => topic_detail::handle_key(event, app),
+/// Longest delay between the two clicks of a double click.
+const DOUBLE_CLICK: Duration = Duration::from_millis(500);
+
+pub fn handle_key(key: KeyCode, app: &mut App) {
+    let action = app.keymap.action(&app.context, key);
+    // Any key closes the help, the quit key still quits.
+    if app.show_help {
+        app.show_help = false;
+        if action != Some(Action::Quit) {
+            return;
+        }
+    }
+    // The keys typed in the topic filter are text.
+    if app.is_editing() {
+        return topic_list::handle_search_key(key, app);
+    }
+    let action = match action {
+        Some(action) => action,
+        None => return,
+    };
+    match action {
+        // Actions of every page.
+        Action::Quit => app.quit(),
+        Action::Help => app.show_help = true,
+        Action::Refresh => app.refresh(),
+        Action::TogglePause => app.toggle_pause(),
+        _ => handle_page_action(action, app),
+    }
+}
+
+fn handle_page_action(action: Action, app: &mut App) {
+    match app.context {
+        TopicListPage => topic_list::handle_action(action, app),
+        TopicDetailPage => topic_detail::handle_action(action, app),
+        GroupListPage => group_list::handle_action(action, app),
+        GroupDetailPage => group_detail::handle_action(action, app),
+    }
+}
+
+/// Clicks select the rows of the lists and switch the tabs, double clicks open the rows and the
+/// wheel moves the selection of the lists or scrolls the text of the detail pages.
+pub fn handle_mouse(event: MouseEvent, app: &mut App) {
+    // Any click closes the help.
+    if app.show_help {
+        if let MouseEvent::Down(..) = event {
+            app.show_help = false;
+        }
+        return;
+    }
+    if app.is_editing() {
+        return;
+    }
+    match event {
+        MouseEvent::Down(MouseButton::Left, column, row, _) => click(column, row, app),
+        MouseEvent::ScrollDown(..) => scroll(true, app),
+        MouseEvent::ScrollUp(..) => scroll(false, app),
+        _ => {}
+    }
+}
+
+fn click(column: u16, row: u16, app: &mut App) {
+    let contains = |area: &Rect| {
+        column >= area.left() && column < area.right() && row >= area.top() && row < area.bottom()
+    };
+    if let Some(&(_, context)) = app.areas.tabs.iter().find(|(area, _)| contains(area)) {
+        if app.context != context {
+            app.switch_context(context);
+        }
+        return;
+    }
+    let table = match app.context {
+        TopicListPage => app.areas.topic_rows,
+        GroupListPage => app.areas.group_rows,
+        TopicDetailPage | GroupDetailPage => return,
+    };
+    if !contains(&table.rows) {
+        return;
+    }
+    let index = table.offset + (row - table.rows.y) as usize;
+    let selected = match app.context {
+        TopicListPage => app.select_topic_row(index),
+        _ => app.select_group_row(index),
+    };
+    if !selected {
+        return;
+    }
+    let now = Instant::now();
+    let double_click = app
+        .last_click
+        .is_some_and(|(at, clicked)| clicked == index && now - at <= DOUBLE_CLICK);
+    if double_click {
+        app.last_click = None;
+        handle_page_action(Action::Open, app);
+    } else {
+        app.last_click = Some((now, index));
+    }
+}
+
+fn scroll(down: bool, app: &mut App) {
+    match app.context {
+        TopicListPage | GroupListPage => {
+            handle_page_action(if down { Action::Down } else { Action::Up }, app)
+        }
+        TopicDetailPage | GroupDetailPage => app.scroll_detail(down),
     }
 }
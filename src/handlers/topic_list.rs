@@ -1,16 +1,30 @@
 use crate::app::App;
-use crate::model::Event;
+use crate::keymap::Action;
 use crossterm::event::KeyCode;
 
-pub fn handle_key(event: Event<KeyCode>, app: &mut App) {
-    match event {
-        Event::Input(key) => match key {
-            KeyCode::Char('s') => app.change_message(String::from("toto")),
-            KeyCode::Up => app.select_previous_topic(),
-            KeyCode::Down => app.select_next_topic(),
-            KeyCode::Enter => app.select_current_topic(),
-            _ => {}
-        },
-        Event::Tick => app.change_message(format!("A new tick arrive {}", app.message)),
+pub fn handle_search_key(key: KeyCode, app: &mut App) {
+    match key {
+        KeyCode::Esc => app.stop_topic_search(true),
+        KeyCode::Enter => app.stop_topic_search(false),
+        KeyCode::Backspace => app.pop_topic_search(),
+        KeyCode::Char(c) => app.push_topic_search(c),
+        _ => {}
+    }
+}
+
+pub fn handle_action(action: Action, app: &mut App) {
+    match action {
+        Action::NextTab => app.next_tab(),
+        Action::Search => app.start_topic_search(),
+        Action::ToggleInternalTopics => app.toggle_internal_topics(),
+        Action::CycleSort => app.cycle_topic_sort(),
+        Action::ReverseSort => app.reverse_topic_sort(),
+        Action::ClearSearch => app.stop_topic_search(true),
+        Action::Up => app.select_previous_topic(),
+        Action::Down => app.select_next_topic(),
+        Action::First => app.select_first_topic(),
+        Action::Last => app.select_last_topic(),
+        Action::Open => app.select_current_topic(),
+        _ => {}
     }
 }
@@ -1,130 +1,313 @@
+use crate::cluster::ClusterClient;
 use crate::model::{
-    ClusterInfo, GroupInfo, GroupMember, MemberAssignment, PartitionOffsets, TopicDetail, TopicInfo,
+    is_internal_topic, BrokerInfo, ClusterInfo, ClusterOverview, GroupInfo, GroupMember,
+    PartitionInfo, PartitionOffsets, TopicConfig, TopicDetail, TopicInfo,
 };
+use crate::protocol::MemberProtocol;
 
-use crate::utils::read_str;
-use byteorder::{BigEndian, ReadBytesExt};
-
-use log::trace;
+use futures::executor::block_on;
+use log::{trace, warn};
+use rdkafka::admin::{AdminClient, AdminOptions, OwnedResourceSpecifier, ResourceSpecifier};
+use rdkafka::client::DefaultClientContext;
 use rdkafka::config::RDKafkaLogLevel;
 use rdkafka::consumer::{stream_consumer::StreamConsumer, BaseConsumer, Consumer};
-use rdkafka::metadata::Metadata;
-use rdkafka::ClientConfig;
-use std::io::Cursor;
-use std::io::Error;
+use rdkafka::error::KafkaResult;
+use rdkafka::metadata::{Metadata, MetadataTopic};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
+use std::collections::{HashMap, HashSet};
+use std::process;
 use std::str;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 pub struct KafkaWrapper {
+    config: ClientConfig,
     consumer: BaseConsumer,
+    admin: AdminClient<DefaultClientContext>,
 }
 
 impl KafkaWrapper {
-    pub fn new(brokers: &str) -> KafkaWrapper {
-        KafkaWrapper {
-            consumer: build_consumer(brokers),
-        }
+    /// Fails when the clients can't be created, for instance from a missing certificate, but
+    /// not when the brokers are unreachable, which only fails the requests.
+    pub fn new(brokers: &str) -> KafkaResult<KafkaWrapper> {
+        KafkaWrapper::from_config(&client_config(brokers))
+    }
+
+    /// Connect with the given client configuration rather than the application one.
+    pub fn from_config(config: &ClientConfig) -> KafkaResult<KafkaWrapper> {
+        Ok(KafkaWrapper {
+            config: config.clone(),
+            consumer: config.create()?,
+            admin: config.create()?,
+        })
     }
 
-    fn get_metadata(&self) -> Metadata {
+    fn get_metadata(&self) -> KafkaResult<Metadata> {
         let timeout: u64 = 3000;
         self.consumer
             .fetch_metadata(None, Duration::from_millis(timeout))
-            .expect("Failed to fetch metadata")
-    }
-
-    pub fn get_cluster_infos(&self) -> ClusterInfo {
-        let metadata = self.get_metadata();
-
-        ClusterInfo {
-            broker_count: metadata.brokers().len(),
-            topic_count: metadata.topics().len(),
-            broker_name: metadata.orig_broker_name().to_string(),
-            broker_id: metadata.orig_broker_id(),
-        }
     }
 
-    pub fn get_topic_infos(&self) -> Vec<TopicInfo> {
-        trace!("get topics infos");
-        let metadata = self.get_metadata();
+    /// Build the topic infos from their watermarks, with a single request for the configs of all
+    /// the topics. DescribeConfigs may be denied to a client allowed everything else: the broker
+    /// defaults are then assumed rather than failing the whole list.
+    fn build_topic_infos(
+        &self,
+        topics: &[&MetadataTopic],
+        watermarks: &HashMap<(String, i32), (i64, i64)>,
+        groups: &[GroupInfo],
+    ) -> Vec<TopicInfo> {
+        let names: Vec<String> = topics.iter().map(|t| t.name().to_owned()).collect();
+        let mut configs = self.get_topic_configs(&names).unwrap_or_else(|e| {
+            warn!("Failed to describe topic configs: {}", e);
+            HashMap::new()
+        });
 
         let mut topic_infos = Vec::new();
 
-        for topic in metadata.topics() {
+        for topic in topics {
             let name = topic.name().to_owned();
             let nb_partitions = topic.partitions().len();
+            let message_count = topic
+                .partitions()
+                .iter()
+                .filter_map(|p| watermarks.get(&(name.to_owned(), p.id())))
+                .map(|(low, high)| high - low)
+                .sum();
+            let high_watermark = topic
+                .partitions()
+                .iter()
+                .filter_map(|p| watermarks.get(&(name.to_owned(), p.id())))
+                .map(|(_, high)| high)
+                .sum();
+            let replication_factor = topic
+                .partitions()
+                .iter()
+                .map(|p| p.replicas().len())
+                .max()
+                .unwrap_or(0);
+            let under_replicated = topic
+                .partitions()
+                .iter()
+                .filter(|p| p.isr().len() < p.replicas().len())
+                .count();
+            let group_count = groups.iter().filter(|g| g.consume_topic(&name)).count();
+            let config = configs.remove(&name).unwrap_or_default();
             topic_infos.push(TopicInfo {
                 name,
                 nb_partitions,
+                message_count,
+                high_watermark,
+                replication_factor,
+                config,
+                group_count,
+                under_replicated,
             })
         }
         topic_infos
     }
 
-    pub fn get_group_infos(&self) -> Vec<GroupInfo> {
+    /// Fetch the watermarks of all the partitions of the metadata with two batched requests
+    /// whatever the number of topics.
+    fn topic_infos(
+        &self,
+        metadata: &Metadata,
+        groups: &[GroupInfo],
+    ) -> KafkaResult<Vec<TopicInfo>> {
+        let topics: Vec<&MetadataTopic> = metadata.topics().iter().collect();
+        let partitions: Vec<(String, i32)> = topics
+            .iter()
+            .flat_map(|t| {
+                t.partitions()
+                    .iter()
+                    .map(move |p| (t.name().to_owned(), p.id()))
+            })
+            .collect();
+        let watermarks = self.get_watermarks(&partitions)?;
+        Ok(self.build_topic_infos(&topics, &watermarks, groups))
+    }
+
+    /// ListOffsets takes a timestamp per partition, where `Offset::End` (-1) and
+    /// `Offset::Beginning` (-2) stand for the latest and the earliest offsets.
+    fn list_offsets(
+        &self,
+        partitions: &[(String, i32)],
+        position: Offset,
+    ) -> KafkaResult<HashMap<(String, i32), i64>> {
+        if partitions.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let mut tpl = TopicPartitionList::with_capacity(partitions.len());
+        for (topic, partition) in partitions {
+            tpl.add_partition_offset(topic, *partition, position);
+        }
+        let result = self
+            .consumer
+            .offsets_for_times(tpl, Duration::from_secs(5))?;
+        Ok(result
+            .elements()
+            .iter()
+            .filter_map(|e| match e.offset() {
+                Offset::Offset(offset) => Some(((e.topic().to_owned(), e.partition()), offset)),
+                _ => None,
+            })
+            .collect())
+    }
+}
+
+impl ClusterClient for KafkaWrapper {
+    fn get_topic_infos(&self, groups: &[GroupInfo]) -> KafkaResult<Vec<TopicInfo>> {
+        trace!("get topics infos");
+        self.topic_infos(&self.get_metadata()?, groups)
+    }
+
+    fn get_brokers(&self) -> KafkaResult<Vec<BrokerInfo>> {
+        Ok(broker_infos(&self.get_metadata()?))
+    }
+
+    fn get_partition_infos(&self) -> KafkaResult<Vec<PartitionInfo>> {
+        trace!("get partition infos");
+        Ok(partition_infos(&self.get_metadata()?))
+    }
+
+    fn get_overview(&self, groups: &[GroupInfo]) -> KafkaResult<ClusterOverview> {
+        let metadata = self.get_metadata()?;
+        Ok(ClusterOverview {
+            cluster_info: cluster_info(&metadata),
+            topic_infos: self.topic_infos(&metadata, groups)?,
+            brokers: broker_infos(&metadata),
+            partitions: partition_infos(&metadata),
+        })
+    }
+
+    /// Fetch the configuration of all the given topics with a single DescribeConfigs request.
+    /// A topic whose own description failed is left out, the broker defaults being assumed.
+    fn get_topic_configs(&self, topics: &[String]) -> KafkaResult<HashMap<String, TopicConfig>> {
+        trace!("get topic configs");
+        let mut configs = HashMap::new();
+        if topics.is_empty() {
+            return Ok(configs);
+        }
+        let resources: Vec<ResourceSpecifier> = topics
+            .iter()
+            .map(|t| ResourceSpecifier::Topic(t.as_str()))
+            .collect();
+        let opts = AdminOptions::new().request_timeout(Some(Duration::from_secs(5)));
+        let results = block_on(self.admin.describe_configs(&resources, &opts))?;
+
+        for resource in results.into_iter().filter_map(Result::ok) {
+            if let OwnedResourceSpecifier::Topic(name) = &resource.specifier {
+                let value = |key: &str| resource.get(key).and_then(|e| e.value.to_owned());
+                let default = TopicConfig::default();
+                let min_insync_replicas = value("min.insync.replicas")
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(default.min_insync_replicas);
+                let cleanup_policy = value("cleanup.policy").unwrap_or(default.cleanup_policy);
+                configs.insert(
+                    name.to_owned(),
+                    TopicConfig {
+                        min_insync_replicas,
+                        cleanup_policy,
+                    },
+                );
+            }
+        }
+        Ok(configs)
+    }
+
+    /// Fetch the high watermark of every given partition in a single ListOffsets round trip,
+    /// rather than one `fetch_watermarks` call per partition.
+    fn get_high_watermarks(
+        &self,
+        partitions: &[(String, i32)],
+    ) -> KafkaResult<HashMap<(String, i32), i64>> {
+        self.list_offsets(partitions, Offset::End)
+    }
+
+    /// Fetch the (low, high) watermarks of every given partition with two batched requests.
+    fn get_watermarks(
+        &self,
+        partitions: &[(String, i32)],
+    ) -> KafkaResult<HashMap<(String, i32), (i64, i64)>> {
+        let lows = self.list_offsets(partitions, Offset::Beginning)?;
+        let highs = self.list_offsets(partitions, Offset::End)?;
+        Ok(highs
+            .into_iter()
+            .map(|(tp, high)| {
+                let low = lows.get(&tp).copied().unwrap_or(high);
+                (tp, (low, high))
+            })
+            .collect())
+    }
+
+    fn get_group_infos(&self) -> KafkaResult<Vec<GroupInfo>> {
         trace!("get group infos");
         let mut group_infos: Vec<GroupInfo> = vec![];
         let group_list = self
             .consumer
-            .fetch_group_list(None, Duration::from_millis(60000))
-            .expect("Failed to fetch group list");
+            .fetch_group_list(None, Duration::from_millis(60000))?;
 
         for group in group_list.groups() {
             trace!("group {}", group.name());
             let name = group.name().to_string();
             let state = group.state().to_string();
+            let protocol_type = group.protocol_type().to_string();
+            let protocol = group.protocol().to_string();
             let mut members: Vec<GroupMember> = vec![];
 
-            if group.protocol_type() == "consumer" {
-                for member in group.members() {
-                    let member_id = member.id().to_string();
-                    let client_host = member.client_host().to_string();
-                    let client_id = member.client_id().to_string();
-                    let mut assignments: Vec<MemberAssignment> = vec![];
-                    if let Some(ass) = member.assignment() {
-                        let mut cursor = Cursor::new(ass);
-                        assignments =
-                            parse_member_assignment(&mut cursor).expect("can't read assignments");
-                    }
-                    let group_member = GroupMember {
-                        id: member_id,
-                        client_id,
-                        client_host,
-                        assignments,
-                    };
-                    members.push(group_member);
-                }
+            for member in group.members() {
+                let member_id = member.id().to_string();
+                let client_host = member.client_host().to_string();
+                let client_id = member.client_id().to_string();
+                let protocol = MemberProtocol::decode(
+                    &member_id,
+                    &protocol_type,
+                    &protocol,
+                    member.metadata().unwrap_or_default(),
+                    member.assignment().unwrap_or_default(),
+                );
+                let group_member = GroupMember {
+                    id: member_id,
+                    client_id,
+                    client_host,
+                    protocol,
+                };
+                members.push(group_member);
             }
             let group_info = GroupInfo {
                 name,
                 state,
+                protocol_type,
+                protocol,
                 members,
             };
             group_infos.push(group_info)
         }
-        group_infos
+        group_infos.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(group_infos)
     }
 
-    pub fn get_topic_detail(&self, topic_name: &str) -> Option<TopicDetail> {
-        let metadata = self.get_metadata();
+    fn get_topic_detail(
+        &self,
+        topic_name: &str,
+        groups: &[GroupInfo],
+    ) -> KafkaResult<Option<TopicDetail>> {
+        let metadata = self.get_metadata()?;
 
         let found_topic = metadata.topics().iter().find(|mt| mt.name() == topic_name);
 
         if let Some(topic) = found_topic {
             let mut message_count = 0;
-            let info = TopicInfo {
-                name: topic.name().to_owned(),
-                nb_partitions: topic.partitions().len(),
-            };
             let mut offsets: Vec<PartitionOffsets> = vec![];
+            let mut watermarks = HashMap::new();
             for partition in topic.partitions() {
                 let id = partition.id();
                 let leader = partition.leader();
-                let _isr = partition.isr();
-                let (low, high) = self
-                    .consumer
-                    .fetch_watermarks(topic.name(), partition.id(), Duration::from_secs(1))
-                    .unwrap_or((-1, -1));
+                let (low, high) = self.consumer.fetch_watermarks(
+                    topic.name(),
+                    partition.id(),
+                    Duration::from_secs(1),
+                )?;
+                watermarks.insert((topic.name().to_owned(), id), (low, high));
                 let count = high - low;
                 message_count += count;
                 let partition_offsets = PartitionOffsets {
@@ -136,18 +319,147 @@ impl KafkaWrapper {
                 };
                 offsets.push(partition_offsets);
             }
-            return Some(TopicDetail {
+            let info = self
+                .build_topic_infos(&[topic], &watermarks, groups)
+                .pop()
+                .expect("one topic info per topic");
+            return Ok(Some(TopicDetail {
                 info,
                 message_count,
                 offsets,
-            });
+            }));
         }
-        None
+        Ok(None)
+    }
+
+    fn get_committed_offsets(&self, group: &str) -> KafkaResult<HashMap<(String, i32), i64>> {
+        let partitions = consumed_partitions(&self.get_metadata()?);
+        fetch_committed_offsets(&self.config, group, &partitions)
     }
+
+    /// Read from the offsets with a consumer which never commits, an offset out of range
+    /// falling back on the first available message. The partitions whose message is not read
+    /// within a few seconds are left out.
+    fn get_message_timestamps(
+        &self,
+        offsets: &[((String, i32), i64)],
+    ) -> KafkaResult<HashMap<(String, i32), i64>> {
+        let mut timestamps = HashMap::new();
+        if offsets.is_empty() {
+            return Ok(timestamps);
+        }
+        let mut assignment = TopicPartitionList::new();
+        for ((topic, partition), offset) in offsets {
+            assignment.add_partition_offset(topic, *partition, Offset::Offset(*offset));
+        }
+        // librdkafka refuses to assign partitions without a `group.id`, but it is never joined.
+        let consumer: BaseConsumer = self
+            .config
+            .clone()
+            .set("group.id", &format!("kafui-timestamps-{}", process::id()))
+            .set("enable.auto.commit", "false")
+            .set("auto.offset.reset", "earliest")
+            .create()?;
+        consumer.assign(&assignment)?;
+        let mut read = HashSet::new();
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while read.len() < offsets.len() && Instant::now() < deadline {
+            match consumer.poll(Duration::from_millis(100)) {
+                Some(Ok(message)) => {
+                    let key = (message.topic().to_owned(), message.partition());
+                    if read.insert(key.clone()) {
+                        if let Some(timestamp) = message.timestamp().to_millis() {
+                            timestamps.insert(key, timestamp);
+                        }
+                    }
+                }
+                Some(Err(e)) => warn!("Failed to read the message timestamps: {}", e),
+                None => {}
+            }
+        }
+        Ok(timestamps)
+    }
+}
+
+fn cluster_info(metadata: &Metadata) -> ClusterInfo {
+    ClusterInfo {
+        broker_count: metadata.brokers().len(),
+        topic_count: metadata.topics().len(),
+        broker_name: metadata.orig_broker_name().to_string(),
+        broker_id: metadata.orig_broker_id(),
+    }
+}
+
+fn broker_infos(metadata: &Metadata) -> Vec<BrokerInfo> {
+    metadata
+        .brokers()
+        .iter()
+        .map(|b| BrokerInfo {
+            id: b.id(),
+            host: b.host().to_owned(),
+            port: b.port(),
+        })
+        .collect()
+}
+
+fn partition_infos(metadata: &Metadata) -> Vec<PartitionInfo> {
+    let mut partition_infos = Vec::new();
+    for topic in metadata.topics() {
+        for partition in topic.partitions() {
+            partition_infos.push(PartitionInfo {
+                topic: topic.name().to_owned(),
+                id: partition.id(),
+                leader: partition.leader(),
+                replicas: partition.replicas().to_vec(),
+                isr: partition.isr().to_vec(),
+            })
+        }
+    }
+    partition_infos
+}
+
+/// Partitions of the non internal topics, on which the groups commit their offsets.
+pub fn consumed_partitions(metadata: &Metadata) -> TopicPartitionList {
+    let mut partitions = TopicPartitionList::new();
+    for topic in metadata
+        .topics()
+        .iter()
+        .filter(|t| !is_internal_topic(t.name()))
+    {
+        for partition in topic.partitions() {
+            partitions.add_partition(topic.name(), partition.id());
+        }
+    }
+    partitions
+}
+
+/// Offsets committed by `group` on the given partitions, asked to its coordinator by a consumer
+/// created with the `group.id`, but never subscribed so that it does not join the group.
+/// rdkafka only fetches the offsets of the `group.id` of the client, hence a client per call.
+pub fn fetch_committed_offsets(
+    config: &ClientConfig,
+    group: &str,
+    partitions: &TopicPartitionList,
+) -> KafkaResult<HashMap<(String, i32), i64>> {
+    let consumer: BaseConsumer = config
+        .clone()
+        .set("group.id", group)
+        .set("enable.auto.commit", "false")
+        .create()?;
+    let committed = consumer.committed_offsets(partitions.clone(), Duration::from_secs(10))?;
+    Ok(committed
+        .elements()
+        .iter()
+        .filter_map(|e| match e.offset() {
+            Offset::Offset(offset) => Some(((e.topic().to_owned(), e.partition()), offset)),
+            _ => None,
+        })
+        .collect())
 }
 
-pub fn build_offset_consumer(brokers: &str) -> StreamConsumer {
-    let offset_consumer = ClientConfig::new()
+pub fn client_config(brokers: &str) -> ClientConfig {
+    let mut config = ClientConfig::new();
+    config
         .set("bootstrap.servers", brokers)
         .set(
             "ssl.key.location",
@@ -161,38 +473,27 @@ pub fn build_offset_consumer(brokers: &str) -> StreamConsumer {
             "ssl.ca.location",
             "/home/fblarel/workspaces/kafkahq/certs/dev/aiven-ca.pem",
         )
-        .set("security.protocol", "ssl")
+        .set("security.protocol", "ssl");
+    config
+}
+
+/// A consumer whose partitions are assigned manually and which never commits, so that reading
+/// `__consumer_offsets` leaves no trace on the cluster. librdkafka refuses to assign partitions
+/// without a `group.id`, but the group is never joined: its id is unique to the process so that
+/// it can't be mistaken for the group of another client.
+pub fn build_offset_consumer(
+    config: &ClientConfig,
+    client_id: &str,
+) -> KafkaResult<StreamConsumer> {
+    config
+        .clone()
+        .set("client.id", client_id)
+        .set("group.id", &format!("kafui-offsets-{}", process::id()))
         .set("enable.partition.eof", "true")
-        .set("session.timeout.ms", "30000")
         .set("enable.auto.commit", "false")
-        .set("group.id", "test-offset-reader-group")
-        .set("auto.offset.reset", "smallest")
+        .set("enable.auto.offset.store", "false")
         .set_log_level(RDKafkaLogLevel::Debug)
         .create::<StreamConsumer>()
-        .expect("failed to create offset consumer");
-
-    offset_consumer
-}
-
-fn build_consumer(brokers: &str) -> BaseConsumer {
-    let consumer: BaseConsumer = ClientConfig::new()
-        .set("bootstrap.servers", brokers)
-        .set(
-            "ssl.key.location",
-            "/home/fblarel/workspaces/kafkahq/certs/dev/aiven-service.key",
-        )
-        .set(
-            "ssl.certificate.location",
-            "/home/fblarel/workspaces/kafkahq/certs/dev/aiven-service.cert",
-        )
-        .set(
-            "ssl.ca.location",
-            "/home/fblarel/workspaces/kafkahq/certs/dev/aiven-ca.pem",
-        )
-        .set("security.protocol", "ssl")
-        .create()
-        .expect("Consumer creation failed");
-    consumer
 }
 
 // pub fn print_metadata(brokers: &str) {
@@ -205,31 +506,3 @@ fn build_consumer(brokers: &str) -> BaseConsumer {
 //         AdminOptions::new().borrow(),
 //     );
 // }
-
-// crash si on consomme plusieurs topics?
-fn parse_member_assignment(
-    payload_rdr: &mut Cursor<&[u8]>,
-) -> Result<Vec<MemberAssignment>, Error> {
-    let _version = payload_rdr.read_i16::<BigEndian>()?;
-    let assign_len = payload_rdr.read_i32::<BigEndian>()?;
-    let mut assigns = Vec::with_capacity(assign_len as usize);
-    for _ in 0..assign_len {
-        let topic = read_str(payload_rdr)?.to_owned();
-        let partition_len = payload_rdr.read_i32::<BigEndian>()?;
-        let mut partitions = Vec::with_capacity(partition_len as usize);
-        for _ in 0..partition_len {
-            let partition = payload_rdr.read_i32::<BigEndian>()?;
-            partitions.push(partition);
-        }
-        assigns.push(MemberAssignment { topic, partitions })
-    }
-    Ok(assigns)
-}
-
-// pub fn read_str<'a>(rdr: &'a mut Cursor<&[u8]>) -> Result<&'a str, Error> {
-//     let len = (rdr.read_i16::<BigEndian>())? as usize;
-//     let pos = rdr.position() as usize;
-//     let slice = str::from_utf8(&rdr.get_ref()[pos..(pos + len)]).expect("kaboom");
-//     rdr.consume(len);
-//     Ok(slice)
-// }
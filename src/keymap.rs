@@ -0,0 +1,413 @@
+use crate::app::Context;
+use crate::config::KeysConfig;
+use crossterm::event::KeyCode;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// What a key does, dispatched by the handlers of the current page.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Quit,
+    Help,
+    Refresh,
+    TogglePause,
+    NextTab,
+    Up,
+    Down,
+    First,
+    Last,
+    Open,
+    Back,
+    Search,
+    ClearSearch,
+    ToggleInternalTopics,
+    CycleSort,
+    ReverseSort,
+}
+
+impl Action {
+    /// Short label of the footer.
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::Quit => "quit",
+            Action::Help => "help",
+            Action::Refresh => "refresh",
+            Action::TogglePause => "pause",
+            Action::NextTab => "switch tab",
+            Action::Up => "up",
+            Action::Down => "down",
+            Action::First => "first",
+            Action::Last => "last",
+            Action::Open => "open",
+            Action::Back => "back",
+            Action::Search => "search",
+            Action::ClearSearch => "clear search",
+            Action::ToggleInternalTopics => "internal topics",
+            Action::CycleSort => "sort",
+            Action::ReverseSort => "reverse sort",
+        }
+    }
+
+    /// Description of the help overlay.
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::Quit => "Quit kafui",
+            Action::Help => "Show or hide this help",
+            Action::Refresh => "Refresh the page now",
+            Action::TogglePause => "Pause or resume the automatic refresh",
+            Action::NextTab => "Switch between the topics and the groups",
+            Action::Up => "Select the previous row",
+            Action::Down => "Select the next row",
+            Action::First => "Select the first row",
+            Action::Last => "Select the last row",
+            Action::Open => "Open the selected row",
+            Action::Back => "Go back to the list",
+            Action::Search => "Filter the topics by name",
+            Action::ClearSearch => "Clear the topic filter",
+            Action::ToggleInternalTopics => "Show or hide the internal topics",
+            Action::CycleSort => "Sort the topics by the next column",
+            Action::ReverseSort => "Reverse the sort order",
+        }
+    }
+}
+
+pub struct Binding {
+    pub key: KeyCode,
+    pub action: Action,
+}
+
+/// The key bindings of each page, and the global ones which apply to every page unless the page
+/// binds the same key.
+pub struct Keymap {
+    global: Vec<Binding>,
+    topic_list: Vec<Binding>,
+    topic_detail: Vec<Binding>,
+    group_list: Vec<Binding>,
+    group_detail: Vec<Binding>,
+}
+
+fn bindings(keys: &[(KeyCode, Action)]) -> Vec<Binding> {
+    keys.iter()
+        .map(|&(key, action)| Binding { key, action })
+        .collect()
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        use Action::*;
+        use KeyCode::{Char, Down as DownKey, Enter, Esc, Tab, Up as UpKey};
+        Keymap {
+            global: bindings(&[
+                (Char('?'), Help),
+                (Char('r'), Refresh),
+                (Char('p'), TogglePause),
+                (Char('q'), Quit),
+            ]),
+            topic_list: bindings(&[
+                (UpKey, Up),
+                (Char('k'), Up),
+                (DownKey, Down),
+                (Char('j'), Down),
+                (Char('g'), First),
+                (Char('G'), Last),
+                (Enter, Open),
+                (Tab, NextTab),
+                (Char('/'), Search),
+                (Esc, ClearSearch),
+                (Char('i'), ToggleInternalTopics),
+                (Char('o'), CycleSort),
+                (Char('O'), ReverseSort),
+            ]),
+            topic_detail: bindings(&[(Esc, Back)]),
+            group_list: bindings(&[
+                (UpKey, Up),
+                (Char('k'), Up),
+                (DownKey, Down),
+                (Char('j'), Down),
+                (Char('g'), First),
+                (Char('G'), Last),
+                (Enter, Open),
+                (Tab, NextTab),
+            ]),
+            group_detail: bindings(&[(Esc, Back)]),
+        }
+    }
+}
+
+impl Keymap {
+    /// The default keymap with the keys of the config, which replace the default keys of the
+    /// actions they bind. Fails on unknown keys, on actions which are not in their section, and on
+    /// keys bound to several actions of a page.
+    pub fn new(config: &KeysConfig) -> Result<Keymap, String> {
+        let mut keymap = Keymap::default();
+        let Keymap {
+            global,
+            topic_list,
+            topic_detail,
+            group_list,
+            group_detail,
+        } = &mut keymap;
+        let sections = [
+            ("global", global, &config.global),
+            ("topic_list", topic_list, &config.topic_list),
+            ("topic_detail", topic_detail, &config.topic_detail),
+            ("group_list", group_list, &config.group_list),
+            ("group_detail", group_detail, &config.group_detail),
+        ];
+        for (name, bindings, keys) in sections {
+            remap(name, bindings, keys)?;
+        }
+        check_conflicts("global", keymap.global.iter())?;
+        let pages = [
+            ("topic_list", Context::TopicListPage),
+            ("topic_detail", Context::TopicDetailPage),
+            ("group_list", Context::GroupListPage),
+            ("group_detail", Context::GroupDetailPage),
+        ];
+        for (name, context) in pages.iter() {
+            check_conflicts(name, keymap.page(context).iter().chain(&keymap.global))?;
+        }
+        Ok(keymap)
+    }
+
+    fn page(&self, context: &Context) -> &[Binding] {
+        match context {
+            Context::TopicListPage => &self.topic_list,
+            Context::TopicDetailPage => &self.topic_detail,
+            Context::GroupListPage => &self.group_list,
+            Context::GroupDetailPage => &self.group_detail,
+        }
+    }
+
+    pub fn action(&self, context: &Context, key: KeyCode) -> Option<Action> {
+        self.page(context)
+            .iter()
+            .chain(&self.global)
+            .find(|b| b.key == key)
+            .map(|b| b.action)
+    }
+
+    /// The actions available in a context with their keys, the ones of the page first.
+    pub fn actions(&self, context: &Context) -> Vec<(Action, Vec<KeyCode>)> {
+        let page = self.page(context);
+        // The global keys bound by the page are shadowed.
+        let global = self
+            .global
+            .iter()
+            .filter(|g| !page.iter().any(|b| b.key == g.key));
+        let mut actions: Vec<(Action, Vec<KeyCode>)> = vec![];
+        for binding in page.iter().chain(global) {
+            match actions
+                .iter_mut()
+                .find(|(action, _)| *action == binding.action)
+            {
+                Some((_, keys)) => keys.push(binding.key),
+                None => actions.push((binding.action, vec![binding.key])),
+            }
+        }
+        actions
+    }
+}
+
+/// Replace the keys of the actions of a section by the configured ones, where the first key of
+/// the action was.
+fn remap(
+    section: &str,
+    bindings: &mut Vec<Binding>,
+    keys: &HashMap<Action, Vec<String>>,
+) -> Result<(), String> {
+    let mut actions: Vec<_> = keys.iter().collect();
+    actions.sort();
+    for (&action, keys) in actions {
+        let position = bindings
+            .iter()
+            .position(|b| b.action == action)
+            .ok_or_else(|| format!("no action {} in the {} keys", action.label(), section))?;
+        bindings.retain(|b| b.action != action);
+        for (i, key) in keys.iter().enumerate() {
+            let key = parse_key(key).map_err(|e| format!("{} in the {} keys", e, section))?;
+            bindings.insert(position + i, Binding { key, action });
+        }
+    }
+    Ok(())
+}
+
+fn check_conflicts<'a>(
+    section: &str,
+    bindings: impl Iterator<Item = &'a Binding>,
+) -> Result<(), String> {
+    let mut actions: HashMap<KeyCode, Action> = HashMap::new();
+    for binding in bindings {
+        match actions.insert(binding.key, binding.action) {
+            Some(other) if other != binding.action => {
+                return Err(format!(
+                    "key {} bound to both {} and {} in the {} keys",
+                    key_label(binding.key),
+                    other.label(),
+                    binding.action.label(),
+                    section
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// A key of the config: a character, or the name of a special key like `enter` or `f5`.
+pub fn parse_key(key: &str) -> Result<KeyCode, String> {
+    let mut chars = key.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Ok(KeyCode::Char(c));
+    }
+    let code = match key.to_lowercase().as_str() {
+        "space" => KeyCode::Char(' '),
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "enter" => KeyCode::Enter,
+        "esc" => KeyCode::Esc,
+        "tab" => KeyCode::Tab,
+        "shift-tab" => KeyCode::BackTab,
+        "backspace" => KeyCode::Backspace,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pgup" => KeyCode::PageUp,
+        "pgdown" => KeyCode::PageDown,
+        "del" => KeyCode::Delete,
+        "ins" => KeyCode::Insert,
+        name => match name.strip_prefix('f').and_then(|n| n.parse().ok()) {
+            Some(n) if (1..=12).contains(&n) => KeyCode::F(n),
+            _ => return Err(format!("unknown key {:?}", key)),
+        },
+    };
+    Ok(code)
+}
+
+/// How a key is shown by the footer and the help.
+pub fn key_label(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => String::from("space"),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Up => String::from("↑"),
+        KeyCode::Down => String::from("↓"),
+        KeyCode::Left => String::from("←"),
+        KeyCode::Right => String::from("→"),
+        KeyCode::Enter => String::from("enter"),
+        KeyCode::Esc => String::from("esc"),
+        KeyCode::Tab => String::from("tab"),
+        KeyCode::BackTab => String::from("shift-tab"),
+        KeyCode::Backspace => String::from("backspace"),
+        KeyCode::Home => String::from("home"),
+        KeyCode::End => String::from("end"),
+        KeyCode::PageUp => String::from("pgup"),
+        KeyCode::PageDown => String::from("pgdown"),
+        KeyCode::Delete => String::from("del"),
+        KeyCode::Insert => String::from("ins"),
+        KeyCode::F(n) => format!("F{}", n),
+        KeyCode::Null => String::from("null"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_bindings_shadow_the_global_ones() {
+        let mut keymap = Keymap::default();
+        keymap.topic_detail.push(Binding {
+            key: KeyCode::Char('r'),
+            action: Action::Back,
+        });
+
+        assert_eq!(
+            keymap.action(&Context::TopicDetailPage, KeyCode::Char('r')),
+            Some(Action::Back)
+        );
+        assert_eq!(
+            keymap.action(&Context::TopicListPage, KeyCode::Char('r')),
+            Some(Action::Refresh)
+        );
+        assert_eq!(keymap.action(&Context::TopicListPage, KeyCode::Left), None);
+
+        let actions = keymap.actions(&Context::TopicDetailPage);
+        assert_eq!(
+            actions,
+            vec![
+                (Action::Back, vec![KeyCode::Esc, KeyCode::Char('r')]),
+                (Action::Help, vec![KeyCode::Char('?')]),
+                (Action::TogglePause, vec![KeyCode::Char('p')]),
+                (Action::Quit, vec![KeyCode::Char('q')]),
+            ]
+        );
+    }
+
+    fn keys(json: &str) -> Result<Keymap, String> {
+        Keymap::new(&serde_json::from_str(json).unwrap())
+    }
+
+    #[test]
+    fn binds_the_vim_keys_by_default() {
+        let keymap = Keymap::default();
+        for (key, action) in [('j', Action::Down), ('k', Action::Up), ('G', Action::Last)].iter() {
+            assert_eq!(
+                keymap.action(&Context::GroupListPage, KeyCode::Char(*key)),
+                Some(*action)
+            );
+        }
+    }
+
+    #[test]
+    fn replaces_the_default_keys_by_the_configured_ones() {
+        let keymap = keys(
+            r#"{
+                "global": {"quit": ["x", "F10"]},
+                "topic_detail": {"back": ["backspace", "h"]}
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            keymap.action(&Context::TopicListPage, KeyCode::F(10)),
+            Some(Action::Quit)
+        );
+        assert_eq!(
+            keymap.action(&Context::TopicListPage, KeyCode::Char('q')),
+            None
+        );
+        assert_eq!(keymap.action(&Context::TopicDetailPage, KeyCode::Esc), None);
+        assert_eq!(
+            keymap.actions(&Context::TopicDetailPage)[0],
+            (Action::Back, vec![KeyCode::Backspace, KeyCode::Char('h')])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_bindings() {
+        assert_eq!(
+            keys(r#"{"group_list": {"down": ["ctrl-n"]}}"#).err(),
+            Some(String::from(
+                "unknown key \"ctrl-n\" in the group_list keys"
+            ))
+        );
+        assert_eq!(
+            keys(r#"{"group_detail": {"search": ["/"]}}"#).err(),
+            Some(String::from("no action search in the group_detail keys"))
+        );
+        assert_eq!(
+            keys(r#"{"topic_list": {"search": ["j"]}}"#).err(),
+            Some(String::from(
+                "key j bound to both down and search in the topic_list keys"
+            ))
+        );
+        // A global key shadowed by a page would not be reachable there.
+        assert_eq!(
+            keys(r#"{"global": {"refresh": ["i"]}}"#).err(),
+            Some(String::from(
+                "key i bound to both internal topics and refresh in the topic_list keys"
+            ))
+        );
+    }
+}
@@ -0,0 +1,438 @@
+use crate::cli::{format_table, group_lag, Report};
+use crate::cluster::ClusterClient;
+use crate::utils::{format_duration, now_ms, parse_duration};
+use clap::{App as ClapApp, AppSettings, Arg, ArgGroup, ArgMatches, SubCommand};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Exit code of a check whose thresholds are exceeded, CRITICAL for Nagios.
+pub const EXCEEDED_EXIT_CODE: i32 = 2;
+/// Exit code of a check which can't tell whether the thresholds are exceeded, UNKNOWN for Nagios.
+pub const UNKNOWN_EXIT_CODE: i32 = 3;
+
+pub fn subcommand() -> ClapApp<'static, 'static> {
+    SubCommand::with_name("lag")
+        .about("Lag of the consumer groups")
+        .setting(AppSettings::SubcommandRequiredElseHelp)
+        .subcommand(
+            SubCommand::with_name("check")
+                .about(
+                    "Check the lag of a group against thresholds, exiting with 2 when one is \
+                     exceeded and with 3 when the lag or the age can't be known",
+                )
+                .arg(
+                    Arg::with_name("group")
+                        .long("group")
+                        .help("consumer group to check")
+                        .takes_value(true)
+                        .required(true),
+                )
+                .arg(
+                    Arg::with_name("topic")
+                        .long("topic")
+                        .help("only check the partitions of this topic")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("max-lag")
+                        .long("max-lag")
+                        .help("maximum number of messages left to consume, over all partitions")
+                        .takes_value(true)
+                        .validator(|v| {
+                            v.parse::<i64>()
+                                .map(|_| ())
+                                .map_err(|_| String::from("expected a number of messages"))
+                        }),
+                )
+                .arg(
+                    Arg::with_name("max-age")
+                        .long("max-age")
+                        .help("maximum age of the oldest message left to consume, e.g. 30s or 5m")
+                        .takes_value(true)
+                        .validator(|v| parse_duration(&v).map(|_| ())),
+                )
+                .group(
+                    ArgGroup::with_name("thresholds")
+                        .args(&["max-lag", "max-age"])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+}
+
+/// Thresholds of a check, one of them at least being set.
+struct Thresholds {
+    max_lag: Option<i64>,
+    max_age: Option<Duration>,
+}
+
+#[derive(Serialize)]
+struct PartitionStatus {
+    topic: String,
+    partition: i32,
+    committed: i64,
+    high_watermark: Option<i64>,
+    lag: Option<i64>,
+    /// Age in milliseconds of the oldest message left to consume, 0 without lag.
+    age_ms: Option<i64>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Critical,
+    Unknown,
+}
+
+#[derive(Serialize)]
+pub struct LagReport {
+    group: String,
+    topic: Option<String>,
+    status: Status,
+    /// The thresholds which are exceeded.
+    violations: Vec<String>,
+    /// The partitions whose lag, or age when checked, is not known.
+    unknown: Vec<String>,
+    lag: i64,
+    max_lag: Option<i64>,
+    age_ms: Option<i64>,
+    max_age_ms: Option<i64>,
+    partitions: Vec<PartitionStatus>,
+}
+
+pub fn run(client: &dyn ClusterClient, args: &ArgMatches) -> Result<LagReport, String> {
+    let group = args.value_of("group").unwrap();
+    let topic = args.value_of("topic");
+    let thresholds = Thresholds {
+        max_lag: args.value_of("max-lag").map(|v| v.parse().unwrap()),
+        max_age: args.value_of("max-age").map(|v| parse_duration(v).unwrap()),
+    };
+    check(client, group, topic, &thresholds, now_ms())
+}
+
+fn check(
+    client: &dyn ClusterClient,
+    group: &str,
+    topic: Option<&str>,
+    thresholds: &Thresholds,
+    now_ms: i64,
+) -> Result<LagReport, String> {
+    let offsets: Vec<_> = group_lag(client, group)
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .filter(|o| topic.is_none_or(|t| o.topic == t))
+        .collect();
+    if offsets.is_empty() {
+        return Err(match topic {
+            Some(topic) => format!("No offsets committed by group {} on topic {}", group, topic),
+            None => format!("No offsets committed by group {}", group),
+        });
+    }
+    // Only the partitions with a lag have a message left to consume.
+    let lagging: Vec<((String, i32), i64)> = offsets
+        .iter()
+        .filter(|o| o.lag.unwrap_or(0) > 0)
+        .map(|o| ((o.topic.to_owned(), o.partition), o.committed))
+        .collect();
+    let timestamps = if thresholds.max_age.is_some() {
+        client
+            .get_message_timestamps(&lagging)
+            .map_err(|e| e.to_string())?
+    } else {
+        Default::default()
+    };
+    let partitions: Vec<PartitionStatus> = offsets
+        .into_iter()
+        .map(|o| {
+            let age_ms = match o.lag {
+                Some(lag) if lag > 0 => timestamps
+                    .get(&(o.topic.to_owned(), o.partition))
+                    .map(|timestamp| (now_ms - timestamp).max(0)),
+                Some(_) => Some(0),
+                None => None,
+            };
+            PartitionStatus {
+                topic: o.topic,
+                partition: o.partition,
+                committed: o.committed,
+                high_watermark: o.high_watermark,
+                lag: o.lag,
+                age_ms,
+            }
+        })
+        .collect();
+
+    let lag = partitions.iter().filter_map(|p| p.lag).sum();
+    let age_ms = thresholds
+        .max_age
+        .and(partitions.iter().filter_map(|p| p.age_ms).max());
+    let max_age_ms = thresholds.max_age.map(|age| age.as_millis() as i64);
+    // The lag and the age of the partitions which are known are lower bounds of the whole: a
+    // threshold they exceed is exceeded whatever the unknown partitions hold.
+    let unknown: Vec<String> = partitions
+        .iter()
+        .filter_map(|p| {
+            if p.lag.is_none() {
+                Some(format!("high watermark of {}[{}]", p.topic, p.partition))
+            } else if max_age_ms.is_some() && p.age_ms.is_none() {
+                Some(format!("age of {}[{}]", p.topic, p.partition))
+            } else {
+                None
+            }
+        })
+        .collect();
+    let mut violations = vec![];
+    if let Some(max_lag) = thresholds.max_lag.filter(|&max_lag| lag > max_lag) {
+        violations.push(format!("lag {} > {}", lag, max_lag));
+    }
+    if let (Some(age), Some(max_age)) = (age_ms, max_age_ms) {
+        if age > max_age {
+            violations.push(format!(
+                "oldest message {} > {}",
+                format_ms(age),
+                format_ms(max_age)
+            ));
+        }
+    }
+    Ok(LagReport {
+        group: group.to_owned(),
+        topic: topic.map(str::to_owned),
+        status: if !violations.is_empty() {
+            Status::Critical
+        } else if !unknown.is_empty() {
+            Status::Unknown
+        } else {
+            Status::Ok
+        },
+        violations,
+        unknown,
+        lag,
+        max_lag: thresholds.max_lag,
+        age_ms,
+        max_age_ms,
+        partitions,
+    })
+}
+
+fn format_ms(ms: i64) -> String {
+    format_duration(Duration::from_millis(ms as u64))
+}
+
+impl LagReport {
+    pub fn exit_code(&self) -> i32 {
+        match self.status {
+            Status::Ok => 0,
+            Status::Critical => EXCEEDED_EXIT_CODE,
+            Status::Unknown => UNKNOWN_EXIT_CODE,
+        }
+    }
+}
+
+impl Report for LagReport {
+    fn to_table(&self) -> String {
+        let target = match &self.topic {
+            Some(topic) => format!("group {} on topic {}", self.group, topic),
+            None => format!("group {}", self.group),
+        };
+        let mut text = match self.status {
+            Status::Ok => {
+                let mut summary = format!("OK: {} lag {}", target, self.lag);
+                if let Some(age) = self.age_ms {
+                    summary.push_str(&format!(", oldest message {}", format_ms(age)));
+                }
+                summary
+            }
+            Status::Critical => format!("CRITICAL: {} {}", target, self.violations.join(", ")),
+            Status::Unknown => format!("UNKNOWN: {} unknown {}", target, self.unknown.join(", ")),
+        };
+        text.push_str("\n\n");
+        let unknown = || String::from("-");
+        let rows: Vec<Vec<String>> = self
+            .partitions
+            .iter()
+            .map(|p| {
+                vec![
+                    p.topic.to_owned(),
+                    p.partition.to_string(),
+                    p.committed.to_string(),
+                    p.high_watermark.map_or_else(unknown, |h| h.to_string()),
+                    p.lag.map_or_else(unknown, |l| l.to_string()),
+                    p.age_ms.map_or_else(unknown, format_ms),
+                ]
+            })
+            .collect();
+        text.push_str(&format_table(
+            &["TOPIC", "PARTITION", "COMMITTED", "HIGH", "LAG", "AGE"],
+            &rows,
+        ));
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fake_cluster::FakeCluster;
+
+    const NOW: i64 = 1_600_000_000_000;
+
+    fn cluster() -> FakeCluster {
+        let mut cluster = FakeCluster::sample();
+        cluster.produce("orders", 0, 100);
+        cluster.produce_at("orders", 0, NOW - 7 * 60 * 1000);
+        cluster.produce_at("orders", 0, NOW - 60 * 1000);
+        cluster.produce("orders", 1, 10);
+        cluster.produce("payments", 0, 5);
+        cluster.commit("billing", "orders", 0, 100);
+        cluster.commit("billing", "orders", 1, 10);
+        cluster.commit("billing", "payments", 0, 2);
+        cluster
+    }
+
+    fn thresholds(max_lag: Option<i64>, max_age: Option<&str>) -> Thresholds {
+        Thresholds {
+            max_lag,
+            max_age: max_age.map(|age| parse_duration(age).unwrap()),
+        }
+    }
+
+    #[test]
+    fn passes_under_the_thresholds() {
+        let report = check(
+            &cluster(),
+            "billing",
+            Some("orders"),
+            &thresholds(Some(2), Some("10m")),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(report.status, Status::Ok);
+        assert_eq!(report.exit_code(), 0);
+        assert_eq!(report.lag, 2);
+        assert_eq!(report.age_ms, Some(7 * 60 * 1000));
+        assert_eq!(
+            report.to_table(),
+            "OK: group billing on topic orders lag 2, oldest message 7m00s\n\n\
+             TOPIC   PARTITION  COMMITTED  HIGH  LAG  AGE\n\
+             orders  0          100        102   2    7m00s\n\
+             orders  1          10         10    0    0s\n"
+        );
+    }
+
+    #[test]
+    fn fails_over_the_thresholds() {
+        let report = check(
+            &cluster(),
+            "billing",
+            None,
+            &thresholds(Some(4), Some("5m")),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(report.status, Status::Critical);
+        assert_eq!(report.exit_code(), EXCEEDED_EXIT_CODE);
+        assert_eq!(
+            report.violations,
+            vec!["lag 5 > 4", "oldest message 7m00s > 5m00s"]
+        );
+        assert!(report
+            .to_table()
+            .starts_with("CRITICAL: group billing lag 5 > 4, oldest message 7m00s > 5m00s\n"));
+    }
+
+    #[test]
+    fn reports_the_unknown_ages_of_messages_without_timestamps() {
+        let report = check(
+            &cluster(),
+            "billing",
+            Some("payments"),
+            &thresholds(None, Some("1m")),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(report.status, Status::Unknown);
+        assert_eq!(report.exit_code(), UNKNOWN_EXIT_CODE);
+        assert_eq!(report.lag, 3);
+        assert_eq!(report.age_ms, None);
+        assert!(report
+            .to_table()
+            .starts_with("UNKNOWN: group billing on topic payments unknown age of payments[0]\n"));
+        // The age is only needed by the age threshold.
+        let report = check(
+            &cluster(),
+            "billing",
+            Some("payments"),
+            &thresholds(Some(3), None),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(report.status, Status::Ok);
+    }
+
+    #[test]
+    fn reports_the_unknown_lag_of_partitions_without_watermark() {
+        let mut cluster = cluster();
+        cluster.commit("billing", "orders", 2, 10);
+        let report = check(
+            &cluster,
+            "billing",
+            Some("orders"),
+            &thresholds(Some(2), None),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(report.status, Status::Unknown);
+        assert_eq!(report.exit_code(), UNKNOWN_EXIT_CODE);
+        assert_eq!(report.unknown, vec!["high watermark of orders[2]"]);
+        // Known partitions over the threshold are enough to fail.
+        let report = check(
+            &cluster,
+            "billing",
+            Some("orders"),
+            &thresholds(Some(1), None),
+            NOW,
+        )
+        .unwrap();
+        assert_eq!(report.status, Status::Critical);
+    }
+
+    #[test]
+    fn fails_without_committed_offsets() {
+        let cluster = cluster();
+        assert_eq!(
+            check(&cluster, "audit", None, &thresholds(Some(0), None), NOW).err(),
+            Some(String::from("No offsets committed by group audit"))
+        );
+        assert_eq!(
+            check(
+                &cluster,
+                "billing",
+                Some("unknown"),
+                &thresholds(Some(0), None),
+                NOW
+            )
+            .err(),
+            Some(String::from(
+                "No offsets committed by group billing on topic unknown"
+            ))
+        );
+    }
+
+    #[test]
+    fn requires_a_threshold() {
+        let args = crate::args()
+            .get_matches_from_safe(vec!["kafui", "lag", "check", "--group", "billing"]);
+        assert!(args.is_err());
+        let args = crate::args().get_matches_from_safe(vec![
+            "kafui",
+            "lag",
+            "check",
+            "--group",
+            "billing",
+            "--max-age",
+            "5 minutes",
+        ]);
+        assert!(args.is_err());
+    }
+}
@@ -2,20 +2,38 @@ use clap::{App as ClapApp, Arg};
 use log::{error, info};
 
 use crate::config::Config;
+use crate::kafka::KafkaWrapper;
+use crate::keymap::Keymap;
+use std::io::{self, Write};
 use std::process;
 
+mod alerts;
 mod app;
+mod cli;
+mod cluster;
 mod config;
+mod exporter;
+#[cfg(test)]
+mod fake_cluster;
 mod handlers;
 mod kafka;
+mod keymap;
+mod lag_check;
+#[cfg(test)]
+mod mock_cluster;
 mod model;
 mod offsets_consumer;
+mod offsets_source;
+mod protocol;
+mod rates;
+mod refresher;
+mod theme;
 mod ui;
 mod utils;
 
-fn main() {
-    // get args
-    let matches = ClapApp::new("kafui")
+/// Arguments of the command line: the UI starts unless a subcommand is given.
+fn args() -> ClapApp<'static, 'static> {
+    ClapApp::new("kafui")
         .name("kafui")
         .version(option_env!("CARGO_PKG_VERSION").unwrap_or(""))
         .about("Fetch and print the kafka cluster metadata")
@@ -36,13 +54,61 @@ fn main() {
                 .takes_value(true)
                 .default_value("config.json"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("profile")
+                .short("p")
+                .long("profile")
+                .help("cluster profile of the config file to use")
+                .takes_value(true),
+        )
+        .arg(cli::output_arg())
+        .subcommands(cli::subcommands())
+        .subcommand(exporter::subcommand())
+}
+
+fn main() {
+    // get args
+    let matches = args().get_matches();
 
     env_logger::init();
-    info!("Starting application");
 
-    let config = Config::new(&matches);
-    if let Err(e) = app::run(config) {
+    let config = Config::new(&matches).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    if let ("exporter", Some(args)) = matches.subcommand() {
+        let listen = args.value_of("listen").unwrap().parse().unwrap();
+        if let Err(e) = exporter::run(config, listen) {
+            error!("exporter failed with error {}", e);
+            process::exit(1);
+        }
+        return;
+    }
+    if matches.subcommand_name().is_some() {
+        let output = KafkaWrapper::new(&config.brokers)
+            .map_err(|e| e.to_string())
+            .and_then(|client| cli::run(&client, &matches));
+        match output {
+            Ok(output) => {
+                print!("{}", output.text);
+                // exit does not flush stdout
+                let _ = io::stdout().flush();
+                process::exit(output.exit_code);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(1);
+            }
+        }
+    }
+
+    // Invalid key bindings are reported before the terminal is taken over.
+    let keymap = Keymap::new(&config.keys).unwrap_or_else(|e| {
+        eprintln!("Invalid key bindings: {}", e);
+        process::exit(1);
+    });
+    info!("Starting application");
+    if let Err(e) = app::run(config, keymap) {
         error!("application failed with error {}", e);
         process::exit(1);
     }
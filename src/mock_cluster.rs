@@ -0,0 +1,559 @@
+//! Integration tests against the mock cluster embedded in librdkafka, which runs brokers in
+//! process so that the rdkafka clients are exercised without Docker or a real cluster.
+//!
+//! The mock of librdkafka 1.5 implements the produce, fetch, metadata, offsets and consumer
+//! group protocols, but neither ListGroups/DescribeGroups nor DescribeConfigs, and the offsets
+//! committed by the groups are not written to `__consumer_offsets`. Its ListOffsets v1 is broken,
+//! so the batched watermarks of `offsets_for_times` are not available either. The groups are
+//! listed and described by a stub coordinator instead, which a real consumer joins.
+
+use crate::cluster::ClusterClient;
+use crate::kafka::KafkaWrapper;
+use crate::model::{OffsetsStore, TopicConfig};
+use crate::offsets_consumer::OffsetsConsumer;
+use crate::protocol::MemberProtocol;
+use crate::utils::{now_ms, read_array_len, read_bytes, read_nullable_str, read_str};
+use byteorder::{BigEndian, ReadBytesExt};
+use libc::{c_char, c_int, c_void};
+use rdkafka::consumer::{BaseConsumer, CommitMode, Consumer};
+use rdkafka::producer::{BaseProducer, BaseRecord};
+use rdkafka::types::{RDKafka, RDKafkaRespErr};
+use rdkafka::{ClientConfig, Offset, TopicPartitionList};
+use std::ffi::{CStr, CString};
+use std::io::{Cursor, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// Not exposed by rdkafka-sys, but exported by the librdkafka it builds.
+extern "C" {
+    fn rd_kafka_handle_mock_cluster(rk: *const RDKafka) -> *mut c_void;
+    fn rd_kafka_mock_cluster_bootstraps(mcluster: *const c_void) -> *const c_char;
+    fn rd_kafka_mock_topic_create(
+        mcluster: *mut c_void,
+        topic: *const c_char,
+        partition_cnt: c_int,
+        replication_factor: c_int,
+    ) -> RDKafkaRespErr;
+    fn rd_kafka_mock_set_apiversion(
+        mcluster: *mut c_void,
+        api_key: i16,
+        min_version: i16,
+        max_version: i16,
+    ) -> RDKafkaRespErr;
+}
+
+const LIST_OFFSETS_API_KEY: i16 = 2;
+
+/// A mock cluster, living as long as the producer which created it.
+struct MockCluster {
+    producer: BaseProducer,
+    bootstrap_servers: String,
+}
+
+impl MockCluster {
+    fn new(brokers: usize) -> MockCluster {
+        let producer: BaseProducer = ClientConfig::new()
+            .set("test.mock.num.brokers", &brokers.to_string())
+            .create()
+            .expect("Can't create the mock cluster");
+        let bootstrap_servers = unsafe {
+            let mock = rd_kafka_handle_mock_cluster(producer.client().native_ptr());
+            assert!(!mock.is_null(), "no mock cluster");
+            // The mock misreads ListOffsets v1 requests, which makes `offsets_for_times` time
+            // out: limit it to v0, for which `offsets_for_times` fails at once.
+            rd_kafka_mock_set_apiversion(mock, LIST_OFFSETS_API_KEY, 0, 0);
+            CStr::from_ptr(rd_kafka_mock_cluster_bootstraps(mock))
+                .to_string_lossy()
+                .into_owned()
+        };
+        MockCluster {
+            producer,
+            bootstrap_servers,
+        }
+    }
+
+    /// Configuration of the clients of the cluster, in plaintext.
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config.set("bootstrap.servers", &self.bootstrap_servers);
+        config
+    }
+
+    fn create_topic(&self, name: &str, partitions: i32, replication_factor: i32) {
+        let topic = CString::new(name).unwrap();
+        let err = unsafe {
+            let mock = rd_kafka_handle_mock_cluster(self.producer.client().native_ptr());
+            rd_kafka_mock_topic_create(mock, topic.as_ptr(), partitions, replication_factor)
+        };
+        assert_eq!(err, RDKafkaRespErr::RD_KAFKA_RESP_ERR_NO_ERROR);
+    }
+
+    fn produce(&self, topic: &str, partition: i32, key: &[u8], payload: Option<&[u8]>) {
+        let mut record = BaseRecord::to(topic).partition(partition).key(key);
+        if let Some(payload) = payload {
+            record = record.payload(payload);
+        }
+        self.producer
+            .send(record)
+            .map_err(|(e, _)| e)
+            .expect("Can't produce");
+        self.producer.flush(Duration::from_secs(5));
+    }
+}
+
+const METADATA_API_KEY: i16 = 3;
+const OFFSET_FETCH_API_KEY: i16 = 9;
+const FIND_COORDINATOR_API_KEY: i16 = 10;
+const JOIN_GROUP_API_KEY: i16 = 11;
+const HEARTBEAT_API_KEY: i16 = 12;
+const LEAVE_GROUP_API_KEY: i16 = 13;
+const SYNC_GROUP_API_KEY: i16 = 14;
+const DESCRIBE_GROUPS_API_KEY: i16 = 15;
+const LIST_GROUPS_API_KEY: i16 = 16;
+
+struct StubMember {
+    id: String,
+    client_id: String,
+    client_host: String,
+    metadata: Vec<u8>,
+    assignment: Vec<u8>,
+}
+
+/// The group of the stub coordinator, of a single member.
+#[derive(Default)]
+struct StubGroup {
+    name: String,
+    protocol_type: String,
+    protocol: String,
+    member: Option<StubMember>,
+}
+
+/// A single broker answering the version 0 of the group requests missing from the mock, and
+/// the requests a consumer needs to join a group through it. The fetches are left unanswered.
+/// Its clients must not ask for the API versions, assuming those of Kafka 0.9.
+struct GroupCoordinatorStub {
+    address: String,
+}
+
+impl GroupCoordinatorStub {
+    /// Serve the (topic, partition count) of `topics` until the end of the tests.
+    fn start(topics: &[(&str, i32)]) -> GroupCoordinatorStub {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let topics: Vec<(String, i32)> = topics
+            .iter()
+            .map(|&(name, partitions)| (name.to_owned(), partitions))
+            .collect();
+        let group = Arc::new(Mutex::new(StubGroup::default()));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let topics = topics.clone();
+                let group = Arc::clone(&group);
+                thread::spawn(move || serve_stub_connection(stream, port, &topics, &group));
+            }
+        });
+        GroupCoordinatorStub {
+            address: format!("127.0.0.1:{}", port),
+        }
+    }
+
+    fn client_config(&self) -> ClientConfig {
+        let mut config = ClientConfig::new();
+        config
+            .set("bootstrap.servers", &self.address)
+            .set("api.version.request", "false")
+            .set("broker.version.fallback", "0.9.0");
+        config
+    }
+}
+
+fn serve_stub_connection(
+    mut stream: TcpStream,
+    port: u16,
+    topics: &[(String, i32)],
+    group: &Mutex<StubGroup>,
+) {
+    let client_host = format!("/{}", stream.peer_addr().unwrap().ip());
+    loop {
+        let mut size = [0u8; 4];
+        if stream.read_exact(&mut size).is_err() {
+            return;
+        }
+        let mut request = vec![0u8; i32::from_be_bytes(size) as usize];
+        if stream.read_exact(&mut request).is_err() {
+            return;
+        }
+        let mut rdr = Cursor::new(&request[..]);
+        let api_key = rdr.read_i16::<BigEndian>().unwrap();
+        let _api_version = rdr.read_i16::<BigEndian>().unwrap();
+        let correlation_id = rdr.read_i32::<BigEndian>().unwrap();
+        let client_id = read_nullable_str(&mut rdr)
+            .unwrap()
+            .unwrap_or_default()
+            .to_owned();
+        let mut group = group.lock().unwrap();
+        let body = match api_key {
+            METADATA_API_KEY => stub_metadata(port, topics),
+            FIND_COORDINATOR_API_KEY => {
+                let mut body = 0i16.to_be_bytes().to_vec();
+                body.extend_from_slice(&0i32.to_be_bytes());
+                put_str(&mut body, "127.0.0.1");
+                body.extend_from_slice(&(port as i32).to_be_bytes());
+                body
+            }
+            JOIN_GROUP_API_KEY => stub_join(&mut rdr, &mut group, &client_id, &client_host),
+            SYNC_GROUP_API_KEY => stub_sync(&mut rdr, &mut group),
+            HEARTBEAT_API_KEY | LEAVE_GROUP_API_KEY => 0i16.to_be_bytes().to_vec(),
+            OFFSET_FETCH_API_KEY => stub_offset_fetch(&mut rdr),
+            LIST_GROUPS_API_KEY => {
+                let mut body = 0i16.to_be_bytes().to_vec();
+                body.extend_from_slice(&(group.member.is_some() as i32).to_be_bytes());
+                if group.member.is_some() {
+                    put_str(&mut body, &group.name);
+                    put_str(&mut body, &group.protocol_type);
+                }
+                body
+            }
+            DESCRIBE_GROUPS_API_KEY => stub_describe(&mut rdr, &group),
+            _ => continue,
+        };
+        let mut response = correlation_id.to_be_bytes().to_vec();
+        response.extend_from_slice(&body);
+        let mut frame = (response.len() as i32).to_be_bytes().to_vec();
+        frame.extend_from_slice(&response);
+        if stream.write_all(&frame).is_err() {
+            return;
+        }
+    }
+}
+
+/// The stub as the only broker, leading every partition.
+fn stub_metadata(port: u16, topics: &[(String, i32)]) -> Vec<u8> {
+    let mut body = 1i32.to_be_bytes().to_vec();
+    body.extend_from_slice(&0i32.to_be_bytes());
+    put_str(&mut body, "127.0.0.1");
+    body.extend_from_slice(&(port as i32).to_be_bytes());
+    body.extend_from_slice(&(topics.len() as i32).to_be_bytes());
+    for (name, partitions) in topics {
+        body.extend_from_slice(&0i16.to_be_bytes());
+        put_str(&mut body, name);
+        body.extend_from_slice(&partitions.to_be_bytes());
+        for partition in 0..*partitions {
+            body.extend_from_slice(&0i16.to_be_bytes());
+            body.extend_from_slice(&partition.to_be_bytes());
+            // Leader, then the replicas and the ISR, broker 0 alone.
+            for value in &[0i32, 1, 0, 1, 0] {
+                body.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+    }
+    body
+}
+
+/// Make the joining member the leader of the first protocol it supports.
+fn stub_join(
+    rdr: &mut Cursor<&[u8]>,
+    group: &mut StubGroup,
+    client_id: &str,
+    client_host: &str,
+) -> Vec<u8> {
+    group.name = read_str(rdr).unwrap().to_owned();
+    let _session_timeout = rdr.read_i32::<BigEndian>().unwrap();
+    let member_id = match read_str(rdr).unwrap() {
+        "" => format!("{}-1", client_id),
+        id => id.to_owned(),
+    };
+    group.protocol_type = read_str(rdr).unwrap().to_owned();
+    read_array_len(rdr, 6).unwrap();
+    group.protocol = read_str(rdr).unwrap().to_owned();
+    let metadata = read_bytes(rdr).unwrap().to_vec();
+
+    let mut body = 0i16.to_be_bytes().to_vec();
+    body.extend_from_slice(&1i32.to_be_bytes());
+    put_str(&mut body, &group.protocol);
+    put_str(&mut body, &member_id);
+    put_str(&mut body, &member_id);
+    body.extend_from_slice(&1i32.to_be_bytes());
+    put_str(&mut body, &member_id);
+    put_bytes(&mut body, &metadata);
+    group.member = Some(StubMember {
+        id: member_id,
+        client_id: client_id.to_owned(),
+        client_host: client_host.to_owned(),
+        metadata,
+        assignment: vec![],
+    });
+    body
+}
+
+/// Keep the assignment the leader computed for itself.
+fn stub_sync(rdr: &mut Cursor<&[u8]>, group: &mut StubGroup) -> Vec<u8> {
+    let _group = read_str(rdr).unwrap();
+    let _generation = rdr.read_i32::<BigEndian>().unwrap();
+    let _member_id = read_str(rdr).unwrap();
+    let assignments = read_array_len(rdr, 6).unwrap();
+    let mut assigned = vec![];
+    for _ in 0..assignments {
+        let _member = read_str(rdr).unwrap();
+        assigned = read_bytes(rdr).unwrap().to_vec();
+    }
+    let mut body = 0i16.to_be_bytes().to_vec();
+    put_bytes(&mut body, &assigned);
+    if let Some(member) = &mut group.member {
+        member.assignment = assigned;
+    }
+    body
+}
+
+/// No offset committed: the consumer starts from the beginning of its partitions.
+fn stub_offset_fetch(rdr: &mut Cursor<&[u8]>) -> Vec<u8> {
+    let _group = read_str(rdr).unwrap();
+    let topics = read_array_len(rdr, 6).unwrap();
+    let mut body = (topics as i32).to_be_bytes().to_vec();
+    for _ in 0..topics {
+        put_str(&mut body, read_str(rdr).unwrap());
+        let partitions = read_array_len(rdr, 4).unwrap();
+        body.extend_from_slice(&(partitions as i32).to_be_bytes());
+        for _ in 0..partitions {
+            body.extend_from_slice(&rdr.read_i32::<BigEndian>().unwrap().to_be_bytes());
+            body.extend_from_slice(&0i64.to_be_bytes());
+            put_str(&mut body, "");
+            body.extend_from_slice(&0i16.to_be_bytes());
+        }
+    }
+    body
+}
+
+fn stub_describe(rdr: &mut Cursor<&[u8]>, group: &StubGroup) -> Vec<u8> {
+    let names = read_array_len(rdr, 2).unwrap();
+    let mut body = (names as i32).to_be_bytes().to_vec();
+    for _ in 0..names {
+        let name = read_str(rdr).unwrap();
+        let member = group.member.as_ref().filter(|_| name == group.name);
+        body.extend_from_slice(&0i16.to_be_bytes());
+        put_str(&mut body, name);
+        put_str(&mut body, if member.is_some() { "Stable" } else { "Dead" });
+        put_str(&mut body, &group.protocol_type);
+        put_str(&mut body, &group.protocol);
+        body.extend_from_slice(&(member.is_some() as i32).to_be_bytes());
+        if let Some(member) = member {
+            put_str(&mut body, &member.id);
+            put_str(&mut body, &member.client_id);
+            put_str(&mut body, &member.client_host);
+            put_bytes(&mut body, &member.metadata);
+            put_bytes(&mut body, &member.assignment);
+        }
+    }
+    body
+}
+
+/// Retry `check` until it succeeds, the clients doing their requests in the background.
+fn eventually<F: FnMut() -> bool>(what: &str, mut check: F) {
+    let deadline = Instant::now() + Duration::from_secs(20);
+    while !check() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+fn put_str(buf: &mut Vec<u8>, s: &str) {
+    buf.extend_from_slice(&(s.len() as i16).to_be_bytes());
+    buf.extend_from_slice(s.as_bytes());
+}
+
+fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+    buf.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+    buf.extend_from_slice(bytes);
+}
+
+fn offset_key(group: &str, topic: &str, partition: i32) -> Vec<u8> {
+    let mut buf = 1i16.to_be_bytes().to_vec();
+    put_str(&mut buf, group);
+    put_str(&mut buf, topic);
+    buf.extend_from_slice(&partition.to_be_bytes());
+    buf
+}
+
+fn offset_value(offset: i64) -> Vec<u8> {
+    let mut buf = 3i16.to_be_bytes().to_vec();
+    buf.extend_from_slice(&offset.to_be_bytes());
+    buf.extend_from_slice(&0i32.to_be_bytes());
+    put_str(&mut buf, "");
+    buf.extend_from_slice(&1_600_000_000_000i64.to_be_bytes());
+    buf
+}
+
+#[test]
+fn fetches_topic_details() {
+    let cluster = MockCluster::new(3);
+    cluster.create_topic("orders", 3, 3);
+    for _ in 0..5 {
+        cluster.produce("orders", 0, b"k", Some(b"v"));
+    }
+    for _ in 0..3 {
+        cluster.produce("orders", 1, b"k", Some(b"v"));
+    }
+    let client = KafkaWrapper::from_config(&cluster.client_config()).unwrap();
+
+    let detail = client.get_topic_detail("orders", &[]).unwrap().unwrap();
+    assert_eq!(detail.message_count, 8);
+    let highs: Vec<(i32, i64, i64)> = detail
+        .offsets
+        .iter()
+        .map(|p| (p.id, p.low, p.high))
+        .collect();
+    assert_eq!(highs, vec![(0, 0, 5), (1, 0, 3), (2, 0, 0)]);
+    assert_eq!(detail.info.nb_partitions, 3);
+    assert_eq!(detail.info.replication_factor, 3);
+    assert_eq!(detail.info.under_replicated, 0);
+    // DescribeConfigs is not implemented by the mock, the broker defaults are assumed.
+    assert_eq!(
+        detail.info.config.cleanup_policy,
+        TopicConfig::default().cleanup_policy
+    );
+    assert!(client.get_topic_detail("unknown", &[]).unwrap().is_none());
+
+    assert_eq!(client.get_brokers().unwrap().len(), 3);
+}
+
+#[test]
+fn reads_message_timestamps() {
+    let cluster = MockCluster::new(1);
+    cluster.create_topic("orders", 1, 1);
+    let before = now_ms();
+    for _ in 0..3 {
+        cluster.produce("orders", 0, b"k", Some(b"v"));
+    }
+    let client = KafkaWrapper::from_config(&cluster.client_config()).unwrap();
+
+    let timestamps = client
+        .get_message_timestamps(&[((String::from("orders"), 0), 1)])
+        .unwrap();
+    let timestamp = timestamps[&(String::from("orders"), 0)];
+    assert!(timestamp >= before && timestamp <= now_ms());
+}
+
+/// The mock can't list groups: `get_group_infos` fails rather than reading as a cluster without
+/// groups.
+#[test]
+fn reports_the_failure_of_the_group_list() {
+    let cluster = MockCluster::new(1);
+    cluster.create_topic("orders", 1, 1);
+    cluster.produce("orders", 0, b"k", Some(b"v"));
+
+    // The group exists on the cluster once it has committed, but can't be listed.
+    let consumer: BaseConsumer = cluster
+        .client_config()
+        .set("group.id", "billing")
+        .create()
+        .unwrap();
+    let mut offsets = TopicPartitionList::new();
+    offsets.add_partition_offset("orders", 0, Offset::Offset(1));
+    consumer.commit(&offsets, CommitMode::Sync).unwrap();
+
+    let client = KafkaWrapper::from_config(&cluster.client_config()).unwrap();
+    assert!(client.get_group_infos().is_err());
+}
+
+/// The subscription and the assignment librdkafka sends when joining a group, as described by
+/// the coordinator. Connect and Streams members are only decoded by the unit tests of the
+/// protocol, librdkafka speaking neither.
+#[test]
+fn decodes_the_members_of_a_consumer_group() {
+    let stub = GroupCoordinatorStub::start(&[("orders", 2), ("payments", 1)]);
+    let consumer: BaseConsumer = stub
+        .client_config()
+        .set("group.id", "billing")
+        .set("client.id", "billing-consumer")
+        .create()
+        .unwrap();
+    consumer.subscribe(&["orders"]).unwrap();
+    eventually("the assignment of the consumer", || {
+        consumer.poll(Duration::from_millis(100));
+        consumer.assignment().is_ok_and(|a| a.count() == 2)
+    });
+
+    let client = KafkaWrapper::from_config(&stub.client_config()).unwrap();
+    // The groups are listed by the brokers known to the client, the bootstrap one not counting.
+    assert_eq!(client.get_brokers().unwrap().len(), 1);
+    let groups = client.get_group_infos().unwrap();
+    assert_eq!(groups.len(), 1);
+    let group = &groups[0];
+    assert_eq!(
+        (
+            group.name.as_str(),
+            group.state.as_str(),
+            group.protocol_type.as_str()
+        ),
+        ("billing", "Stable", "consumer")
+    );
+    assert_eq!(group.members.len(), 1);
+    let member = &group.members[0];
+    assert_eq!(member.client_id, "billing-consumer");
+    assert_eq!(member.client_host, "/127.0.0.1");
+    match &member.protocol {
+        MemberProtocol::Consumer {
+            subscription: Some(subscription),
+            assignment: Some(assignment),
+            streams: None,
+        } => {
+            assert_eq!(subscription.topics, vec!["orders"]);
+            let assigned: Vec<(&str, &[i32])> = assignment
+                .assignments
+                .iter()
+                .map(|a| (a.topic.as_str(), &a.partitions[..]))
+                .collect();
+            assert_eq!(assigned, vec![("orders", &[0, 1][..])]);
+        }
+        _ => panic!("the member is not decoded as a consumer"),
+    }
+}
+
+#[tokio::test(threaded_scheduler)]
+async fn replays_consumer_offsets() {
+    let cluster = MockCluster::new(1);
+    cluster.create_topic("__consumer_offsets", 2, 1);
+    let commits = [
+        ("billing", "orders", 0, 10),
+        ("billing", "orders", 1, 20),
+        ("audit", "orders", 0, 30),
+        ("billing", "orders", 0, 15),
+    ];
+    for &(group, topic, partition, offset) in commits.iter() {
+        let key = offset_key(group, topic, partition);
+        cluster.produce(
+            "__consumer_offsets",
+            partition,
+            &key,
+            Some(&offset_value(offset)),
+        );
+    }
+    // The offsets of the audit group expired.
+    cluster.produce(
+        "__consumer_offsets",
+        0,
+        &offset_key("audit", "orders", 0),
+        None,
+    );
+
+    let store = Arc::new(Mutex::new(OffsetsStore::default()));
+    let consumer =
+        OffsetsConsumer::from_config(&cluster.client_config(), "kafui-test", store.clone())
+            .unwrap();
+    tokio::spawn(consumer.run());
+
+    eventually("the replay", || store.lock().unwrap().replay.is_complete());
+    let store = store.lock().unwrap();
+    let mut committed = store.committed_offsets();
+    committed.sort();
+    assert_eq!(
+        committed,
+        vec![
+            (String::from("billing"), String::from("orders"), 0, 15),
+            (String::from("billing"), String::from("orders"), 1, 20),
+        ]
+    );
+    assert_eq!(store.replay.percentage(), 100);
+}
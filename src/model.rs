@@ -1,12 +1,20 @@
-use crate::utils::read_str;
+use crate::protocol::MemberProtocol;
+use crate::refresher::{Refresh, Snapshot};
+use crate::utils::{read_bytes, read_nullable_str, read_str};
 use byteorder::{BigEndian, ReadBytesExt};
+use crossterm::event::MouseEvent;
 use serde::export::TryFrom;
+use std::collections::HashMap;
 use std::hash::Hash;
 use std::io::{Cursor, Error};
 
 pub enum Event<K> {
     Tick,
     Input(K),
+    Mouse(MouseEvent),
+    Refreshed(Snapshot),
+    /// The data of a page could not be fetched, its previous data being kept.
+    RefreshFailed(Refresh, String),
 }
 
 pub struct MemberAssignment {
@@ -18,18 +26,23 @@ pub struct GroupMember {
     pub id: String,
     pub client_id: String,
     pub client_host: String,
-    pub assignments: Vec<MemberAssignment>,
+    pub protocol: MemberProtocol,
 }
 
 impl GroupMember {
     fn consume_topic(&self, topic: &str) -> bool {
-        self.assignments.iter().any(|ass| ass.topic == topic)
+        self.protocol
+            .assignments()
+            .iter()
+            .any(|ass| ass.topic == topic)
     }
 }
 
 pub struct GroupInfo {
     pub name: String,
     pub state: String,
+    pub protocol_type: String,
+    pub protocol: String,
     pub members: Vec<GroupMember>,
 }
 
@@ -47,9 +60,70 @@ pub struct PartitionOffsets {
     pub leader: i32,
     // isr: [i32],
 }
+
+pub struct PartitionInfo {
+    pub topic: String,
+    pub id: i32,
+    pub leader: i32,
+    pub replicas: Vec<i32>,
+    pub isr: Vec<i32>,
+}
+
+impl PartitionInfo {
+    pub fn is_offline(&self) -> bool {
+        self.leader == -1
+    }
+
+    pub fn is_under_replicated(&self) -> bool {
+        self.isr.len() < self.replicas.len()
+    }
+
+    /// The preferred leader is the first replica of the assignment.
+    pub fn preferred_leader(&self) -> Option<i32> {
+        self.replicas.first().copied()
+    }
+}
+
+pub struct TopicConfig {
+    pub min_insync_replicas: usize,
+    pub cleanup_policy: String,
+}
+
+/// Broker defaults, used when the topic configuration could not be described.
+impl Default for TopicConfig {
+    fn default() -> Self {
+        TopicConfig {
+            min_insync_replicas: 1,
+            cleanup_policy: String::from("delete"),
+        }
+    }
+}
+
+/// The size on disk of the topics is missing on purpose: it is only known from DescribeLogDirs,
+/// which rdkafka 0.24 doesn't implement, and a client of our own would have to reproduce the
+/// security settings of librdkafka.
 pub struct TopicInfo {
     pub name: String,
     pub nb_partitions: usize,
+    pub message_count: i64,
+    /// Sum of the partitions high watermarks, i.e. the number of messages ever produced.
+    pub high_watermark: i64,
+    pub replication_factor: usize,
+    pub config: TopicConfig,
+    pub group_count: usize,
+    pub under_replicated: usize,
+}
+
+impl TopicInfo {
+    pub fn is_internal(&self) -> bool {
+        is_internal_topic(&self.name)
+    }
+}
+
+/// Internal topics are the ones used by kafka itself or its ecosystem (`__consumer_offsets`,
+/// `_schemas`, `__transaction_state`, ...), which are prefixed by an underscore by convention.
+pub fn is_internal_topic(name: &str) -> bool {
+    name.starts_with('_')
 }
 
 pub struct TopicDetail {
@@ -58,6 +132,7 @@ pub struct TopicDetail {
     pub offsets: Vec<PartitionOffsets>,
 }
 
+#[derive(Default)]
 pub struct ClusterInfo {
     pub broker_count: usize,
     pub topic_count: usize,
@@ -65,8 +140,96 @@ pub struct ClusterInfo {
     pub broker_id: i32,
 }
 
+pub struct BrokerInfo {
+    pub id: i32,
+    pub host: String,
+    pub port: i32,
+}
+
+/// What the topic list shows of the cluster, described by a single metadata request.
+pub struct ClusterOverview {
+    pub cluster_info: ClusterInfo,
+    pub topic_infos: Vec<TopicInfo>,
+    pub brokers: Vec<BrokerInfo>,
+    pub partitions: Vec<PartitionInfo>,
+}
+
+pub struct BrokerLeadership {
+    pub broker_id: i32,
+    pub leader_count: usize,
+    pub preferred_count: usize,
+}
+
+#[derive(Default)]
+pub struct ClusterHealth {
+    pub under_replicated: usize,
+    pub offline: usize,
+    pub under_min_isr: usize,
+    pub leadership: Vec<BrokerLeadership>,
+    pub growing_lag_groups: Vec<String>,
+}
+
+impl ClusterHealth {
+    /// Summarise the partitions state. `growing_lag_groups` are the groups whose lag keeps
+    /// increasing.
+    pub fn compute(
+        brokers: &[i32],
+        partitions: &[PartitionInfo],
+        topics: &[TopicInfo],
+        growing_lag_groups: Vec<String>,
+    ) -> ClusterHealth {
+        let mut health = ClusterHealth::default();
+        let min_isr: HashMap<&str, usize> = topics
+            .iter()
+            .map(|t| (t.name.as_str(), t.config.min_insync_replicas))
+            .collect();
+        let mut leadership: Vec<BrokerLeadership> = brokers
+            .iter()
+            .map(|&broker_id| BrokerLeadership {
+                broker_id,
+                leader_count: 0,
+                preferred_count: 0,
+            })
+            .collect();
+
+        for partition in partitions {
+            if partition.is_offline() {
+                health.offline += 1;
+            }
+            if partition.is_under_replicated() {
+                health.under_replicated += 1;
+            }
+            if let Some(&min_insync_replicas) = min_isr.get(partition.topic.as_str()) {
+                if partition.isr.len() < min_insync_replicas {
+                    health.under_min_isr += 1;
+                }
+            }
+            for broker in leadership.iter_mut() {
+                if broker.broker_id == partition.leader {
+                    broker.leader_count += 1;
+                }
+                if Some(broker.broker_id) == partition.preferred_leader() {
+                    broker.preferred_count += 1;
+                }
+            }
+        }
+        leadership.sort_by_key(|b| b.broker_id);
+        health.leadership = leadership;
+        health.growing_lag_groups = growing_lag_groups;
+        health
+    }
+}
+
+/// Value of an offset commit record of `__consumer_offsets`.
 pub struct OffsetValue {
     pub offset: i64,
+    /// Since version 3.
+    pub leader_epoch: Option<i32>,
+    pub metadata: String,
+    /// Unknown when the offsets are fetched from the group coordinator.
+    pub commit_timestamp: Option<i64>,
+    /// Only in version 1, later versions rely on the broker retention settings.
+    pub expire_timestamp: Option<i64>,
 }
 
 impl TryFrom<&[u8]> for OffsetValue {
@@ -74,10 +237,258 @@ impl TryFrom<&[u8]> for OffsetValue {
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
         let mut val_rdr = Cursor::new(bytes);
-        val_rdr.read_i16::<BigEndian>()?;
-        let offset = val_rdr.read_i64::<BigEndian>()?.to_owned();
-        // let metadata = read_str(&mut val_rdr)?.to_owned();
-        Ok(OffsetValue { offset })
+        let version = val_rdr.read_i16::<BigEndian>()?;
+        let offset = val_rdr.read_i64::<BigEndian>()?;
+        let leader_epoch = match version {
+            v if v >= 3 => Some(val_rdr.read_i32::<BigEndian>()?),
+            _ => None,
+        };
+        let metadata = read_str(&mut val_rdr)?.to_owned();
+        let commit_timestamp = val_rdr.read_i64::<BigEndian>()?;
+        let expire_timestamp = match version {
+            1 => Some(val_rdr.read_i64::<BigEndian>()?),
+            _ => None,
+        };
+        Ok(OffsetValue {
+            offset,
+            leader_epoch,
+            metadata,
+            commit_timestamp: Some(commit_timestamp),
+            expire_timestamp,
+        })
+    }
+}
+
+/// A member of a group, as stored by the group coordinator in `__consumer_offsets`.
+pub struct GroupMetadataMember {
+    pub id: String,
+    /// Static membership id, since version 3.
+    pub group_instance_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    /// Since version 1.
+    pub rebalance_timeout: Option<i32>,
+    pub session_timeout: i32,
+    pub protocol: MemberProtocol,
+}
+
+/// Value of a group metadata record of `__consumer_offsets`.
+pub struct GroupMetadata {
+    pub protocol_type: String,
+    pub generation: i32,
+    pub protocol: Option<String>,
+    pub leader: Option<String>,
+    /// Since version 2.
+    pub current_state_timestamp: Option<i64>,
+    pub members: Vec<GroupMetadataMember>,
+}
+
+impl TryFrom<&[u8]> for GroupMetadata {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rdr = Cursor::new(bytes);
+        let version = rdr.read_i16::<BigEndian>()?;
+        let protocol_type = read_str(&mut rdr)?.to_owned();
+        let generation = rdr.read_i32::<BigEndian>()?;
+        let protocol = read_nullable_str(&mut rdr)?.map(str::to_owned);
+        let leader = read_nullable_str(&mut rdr)?.map(str::to_owned);
+        let current_state_timestamp = match version {
+            v if v >= 2 => Some(rdr.read_i64::<BigEndian>()?),
+            _ => None,
+        };
+
+        let members_len = rdr.read_i32::<BigEndian>()?;
+        let mut members = Vec::new();
+        for _ in 0..members_len {
+            let id = read_str(&mut rdr)?.to_owned();
+            let group_instance_id = match version {
+                v if v >= 3 => read_nullable_str(&mut rdr)?.map(str::to_owned),
+                _ => None,
+            };
+            let client_id = read_str(&mut rdr)?.to_owned();
+            let client_host = read_str(&mut rdr)?.to_owned();
+            let rebalance_timeout = match version {
+                v if v >= 1 => Some(rdr.read_i32::<BigEndian>()?),
+                _ => None,
+            };
+            let session_timeout = rdr.read_i32::<BigEndian>()?;
+            // A member whose embedded protocol can't be decoded is still listed, without it.
+            let subscription = read_bytes(&mut rdr)?.to_vec();
+            let assignment = read_bytes(&mut rdr)?;
+            let protocol = MemberProtocol::decode(
+                &id,
+                &protocol_type,
+                protocol.as_deref().unwrap_or_default(),
+                &subscription,
+                assignment,
+            );
+            members.push(GroupMetadataMember {
+                id,
+                group_instance_id,
+                client_id,
+                client_host,
+                rebalance_timeout,
+                session_timeout,
+                protocol,
+            });
+        }
+        Ok(GroupMetadata {
+            protocol_type,
+            generation,
+            protocol,
+            leader,
+            current_state_timestamp,
+            members,
+        })
+    }
+}
+
+struct PartitionReplay {
+    low: i64,
+    high: i64,
+    position: i64,
+    done: bool,
+}
+
+/// Progress of the initial replay of `__consumer_offsets`. Until it completes, the committed
+/// offsets, and the lag computed from them, are incomplete.
+#[derive(Default)]
+pub struct ReplayProgress {
+    partitions: HashMap<i32, PartitionReplay>,
+    finished: bool,
+}
+
+impl ReplayProgress {
+    /// Register the partitions to replay, as (partition, low watermark, high watermark).
+    pub fn start(&mut self, partitions: &[(i32, i64, i64)]) {
+        for &(id, low, high) in partitions {
+            let replay = PartitionReplay {
+                low,
+                high,
+                position: low,
+                done: high <= low,
+            };
+            self.partitions.insert(id, replay);
+        }
+    }
+
+    pub fn advance(&mut self, partition: i32, offset: i64) {
+        if let Some(replay) = self.partitions.get_mut(&partition) {
+            replay.position = offset + 1;
+            replay.done |= replay.position >= replay.high;
+        }
+    }
+
+    pub fn reach_end(&mut self, partition: i32) {
+        if let Some(replay) = self.partitions.get_mut(&partition) {
+            replay.position = replay.position.max(replay.high);
+            replay.done = true;
+        }
+    }
+
+    /// Mark the load as complete, for the sources which load all the offsets at once.
+    pub fn finish(&mut self) {
+        self.finished = true;
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.finished || (!self.partitions.is_empty() && self.partitions.values().all(|p| p.done))
+    }
+
+    pub fn percentage(&self) -> u8 {
+        if self.finished {
+            return 100;
+        }
+        let total: i64 = self.partitions.values().map(|p| p.high - p.low).sum();
+        if total <= 0 {
+            return if self.is_complete() { 100 } else { 0 };
+        }
+        let replayed: i64 = self
+            .partitions
+            .values()
+            .map(|p| p.position.min(p.high) - p.low)
+            .sum();
+        (replayed * 100 / total) as u8
+    }
+}
+
+/// Content of `__consumer_offsets`: the committed offsets and the metadata of each group.
+#[derive(Default)]
+pub struct OffsetsStore {
+    pub offsets: HashMap<OffsetAndMetadata, OffsetValue>,
+    pub groups: HashMap<String, GroupMetadata>,
+    pub replay: ReplayProgress,
+}
+
+impl OffsetsStore {
+    /// Committed offsets of every group, as (group, topic, partition, offset).
+    pub fn committed_offsets(&self) -> Vec<(String, String, i32, i64)> {
+        self.offsets
+            .iter()
+            .filter_map(|(key, value)| match key {
+                OffsetAndMetadata::OffsetKey {
+                    group,
+                    topic,
+                    partition,
+                } => Some((group.to_owned(), topic.to_owned(), *partition, value.offset)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Apply a record of `__consumer_offsets`, a `None` payload being a tombstone which deletes
+    /// the offset or the group.
+    pub fn apply(&mut self, key: &[u8], payload: Option<&[u8]>) -> Result<(), Error> {
+        match (OffsetAndMetadata::try_from(key)?, payload) {
+            (key @ OffsetAndMetadata::OffsetKey { .. }, Some(payload)) => {
+                self.offsets.insert(key, OffsetValue::try_from(payload)?);
+            }
+            (key @ OffsetAndMetadata::OffsetKey { .. }, None) => {
+                self.offsets.remove(&key);
+            }
+            (OffsetAndMetadata::GroupMetadataKey { group }, Some(payload)) => {
+                self.groups.insert(group, GroupMetadata::try_from(payload)?);
+            }
+            (OffsetAndMetadata::GroupMetadataKey { group }, None) => {
+                self.groups.remove(&group);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn committed(&self, group: &str, topic: &str, partition: i32) -> Option<&OffsetValue> {
+        self.offsets.get(&OffsetAndMetadata::OffsetKey {
+            group: group.to_owned(),
+            topic: topic.to_owned(),
+            partition,
+        })
+    }
+
+    /// Committed offsets of a group, as (topic, partition, value), ordered by topic and partition.
+    pub fn group_offsets(&self, group: &str) -> Vec<(&str, i32, &OffsetValue)> {
+        let mut offsets: Vec<(&str, i32, &OffsetValue)> = self
+            .offsets
+            .iter()
+            .filter_map(|(key, value)| match key {
+                OffsetAndMetadata::OffsetKey {
+                    group: g,
+                    topic,
+                    partition,
+                } if g == group => Some((topic.as_str(), *partition, value)),
+                _ => None,
+            })
+            .collect();
+        offsets.sort_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)));
+        offsets
+    }
+
+    /// Timestamp of the most recent offset commit of a group.
+    pub fn last_commit(&self, group: &str) -> Option<i64> {
+        self.group_offsets(group)
+            .iter()
+            .filter_map(|(_, _, value)| value.commit_timestamp)
+            .max()
     }
 }
 
@@ -112,3 +523,134 @@ impl TryFrom<&[u8]> for OffsetAndMetadata {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_str(buf: &mut Vec<u8>, s: &str) {
+        buf.extend_from_slice(&(s.len() as i16).to_be_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn put_bytes(buf: &mut Vec<u8>, bytes: &[u8]) {
+        buf.extend_from_slice(&(bytes.len() as i32).to_be_bytes());
+        buf.extend_from_slice(bytes);
+    }
+
+    fn offset_value(version: i16) -> Vec<u8> {
+        let mut buf = version.to_be_bytes().to_vec();
+        buf.extend_from_slice(&42i64.to_be_bytes());
+        if version >= 3 {
+            buf.extend_from_slice(&5i32.to_be_bytes());
+        }
+        put_str(&mut buf, "checkpoint");
+        buf.extend_from_slice(&1000i64.to_be_bytes());
+        if version == 1 {
+            buf.extend_from_slice(&2000i64.to_be_bytes());
+        }
+        buf
+    }
+
+    fn consumer_assignment() -> Vec<u8> {
+        let mut buf = 0i16.to_be_bytes().to_vec();
+        buf.extend_from_slice(&1i32.to_be_bytes());
+        put_str(&mut buf, "orders");
+        buf.extend_from_slice(&2i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&1i32.to_be_bytes());
+        buf.extend_from_slice(&(-1i32).to_be_bytes());
+        buf
+    }
+
+    fn group_metadata(version: i16) -> Vec<u8> {
+        let mut buf = version.to_be_bytes().to_vec();
+        put_str(&mut buf, "consumer");
+        buf.extend_from_slice(&3i32.to_be_bytes());
+        put_str(&mut buf, "range");
+        put_str(&mut buf, "member-1");
+        if version >= 2 {
+            buf.extend_from_slice(&1500i64.to_be_bytes());
+        }
+        buf.extend_from_slice(&1i32.to_be_bytes());
+        put_str(&mut buf, "member-1");
+        if version >= 3 {
+            put_str(&mut buf, "instance-1");
+        }
+        put_str(&mut buf, "billing-1");
+        put_str(&mut buf, "/10.0.0.1");
+        if version >= 1 {
+            buf.extend_from_slice(&60_000i32.to_be_bytes());
+        }
+        buf.extend_from_slice(&10_000i32.to_be_bytes());
+        put_bytes(&mut buf, &[]);
+        put_bytes(&mut buf, &consumer_assignment());
+        buf
+    }
+
+    #[test]
+    fn decodes_every_offset_value_version() {
+        for version in 0..=3 {
+            let value = OffsetValue::try_from(offset_value(version).as_slice()).unwrap();
+            assert_eq!(value.offset, 42);
+            assert_eq!(value.metadata, "checkpoint");
+            assert_eq!(value.commit_timestamp, Some(1000));
+            let leader_epoch = if version >= 3 { Some(5) } else { None };
+            assert_eq!(value.leader_epoch, leader_epoch, "version {}", version);
+            let expire_timestamp = if version == 1 { Some(2000) } else { None };
+            assert_eq!(
+                value.expire_timestamp, expire_timestamp,
+                "version {}",
+                version
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_every_group_metadata_version() {
+        for version in 0..=3 {
+            let group = GroupMetadata::try_from(group_metadata(version).as_slice()).unwrap();
+            assert_eq!(group.protocol_type, "consumer");
+            assert_eq!(group.generation, 3);
+            assert_eq!(group.protocol.as_deref(), Some("range"));
+            assert_eq!(group.leader.as_deref(), Some("member-1"));
+            let timestamp = if version >= 2 { Some(1500) } else { None };
+            assert_eq!(
+                group.current_state_timestamp, timestamp,
+                "version {}",
+                version
+            );
+
+            let member = &group.members[0];
+            assert_eq!(member.id, "member-1");
+            assert_eq!(member.client_id, "billing-1");
+            assert_eq!(member.client_host, "/10.0.0.1");
+            assert_eq!(member.session_timeout, 10_000);
+            let instance = if version >= 3 {
+                Some("instance-1")
+            } else {
+                None
+            };
+            assert_eq!(member.group_instance_id.as_deref(), instance);
+            let rebalance_timeout = if version >= 1 { Some(60_000) } else { None };
+            assert_eq!(member.rebalance_timeout, rebalance_timeout);
+            let assignments = member.protocol.assignments();
+            assert_eq!(assignments.len(), 1);
+            assert_eq!(assignments[0].partitions, vec![0, 1]);
+        }
+    }
+
+    #[test]
+    fn rejects_truncated_values() {
+        for version in 0..=3 {
+            let bytes = offset_value(version);
+            for len in 0..bytes.len() {
+                assert!(OffsetValue::try_from(&bytes[..len]).is_err());
+            }
+            let bytes = group_metadata(version);
+            for len in 0..bytes.len() {
+                assert!(GroupMetadata::try_from(&bytes[..len]).is_err());
+            }
+        }
+    }
+}
@@ -1,34 +1,63 @@
 use crate::kafka;
-use crate::model::OffsetAndMetadata::OffsetKey;
-use crate::model::{OffsetAndMetadata, OffsetValue};
+use crate::model::OffsetsStore;
 use futures::StreamExt;
 use log::warn;
 use rdkafka::consumer::{Consumer, StreamConsumer};
-use rdkafka::Message;
-use std::collections::HashMap;
-use std::convert::TryFrom;
+use rdkafka::error::{KafkaError, KafkaResult};
+use rdkafka::{ClientConfig, Message, Offset, TopicPartitionList};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const OFFSETS_TOPIC: &str = "__consumer_offsets";
 
 pub struct OffsetsConsumer {
     pub consumer: StreamConsumer,
-    pub offsets: Arc<Mutex<HashMap<OffsetAndMetadata, OffsetValue>>>,
+    pub offsets: Arc<Mutex<OffsetsStore>>,
 }
 
 impl OffsetsConsumer {
-    pub async fn get_consumer_group_offsets(
-        brokers: Arc<String>,
-        offsets: Arc<Mutex<HashMap<OffsetAndMetadata, OffsetValue>>>,
-    ) {
-        let consumer = kafka::build_offset_consumer(brokers.as_str());
-        consumer
-            .subscribe(&vec!["__consumer_offsets"])
-            .expect("Can't subscribe to specified topics");
+    pub fn new(
+        brokers: &str,
+        client_id: &str,
+        offsets: Arc<Mutex<OffsetsStore>>,
+    ) -> KafkaResult<OffsetsConsumer> {
+        OffsetsConsumer::from_config(&kafka::client_config(brokers), client_id, offsets)
+    }
+
+    pub fn from_config(
+        config: &ClientConfig,
+        client_id: &str,
+        offsets: Arc<Mutex<OffsetsStore>>,
+    ) -> KafkaResult<OffsetsConsumer> {
+        Ok(OffsetsConsumer {
+            consumer: kafka::build_offset_consumer(config, client_id)?,
+            offsets,
+        })
+    }
+
+    /// Replay `__consumer_offsets` from the beginning, then keep following it.
+    pub async fn run(self) {
+        loop {
+            match self
+                .start_replay()
+                .and_then(|partitions| self.assign(&partitions))
+            {
+                Ok(()) => break,
+                Err(e) => {
+                    warn!("Can't start the replay of {}: {}", OFFSETS_TOPIC, e);
+                    tokio::time::delay_for(Duration::from_secs(5)).await;
+                }
+            }
+        }
 
-        let mut message_stream = consumer.start();
+        let mut message_stream = self.consumer.start();
 
         while let Some(message) = message_stream.next().await {
             match message {
-                Err(_e) => (), //TODO push to hashmap only after the first EOF event?
+                Err(KafkaError::PartitionEOF(partition)) => {
+                    self.offsets.lock().unwrap().replay.reach_end(partition)
+                }
+                Err(e) => warn!("Error while consuming {}: {}", OFFSETS_TOPIC, e),
                 Ok(m) => {
                     let key = match m.key_view::<[u8]>() {
                         None => &[],
@@ -39,28 +68,64 @@ impl OffsetsConsumer {
                         }
                     };
 
+                    // A null payload is a tombstone: the group or its offsets were deleted or
+                    // expired.
                     let payload = match m.payload_view::<[u8]>() {
-                        Some(Ok(s)) => s,
-                        None => &[],
+                        Some(Ok(s)) if !s.is_empty() => Some(s),
+                        Some(Ok(_)) | None => None,
                         Some(Err(e)) => {
                             warn!("Error while deserializing message payload: {:?}", e);
-                            &[]
+                            continue;
                         }
                     };
 
-                    let mut t = offsets.lock().unwrap();
-                    if let (Ok(key), Ok(value)) = (
-                        OffsetAndMetadata::try_from(key),
-                        OffsetValue::try_from(payload),
-                    ) {
-                        if let OffsetKey { .. } = key {
-                            t.insert(key, value);
-                        }
+                    let mut t = self.offsets.lock().unwrap();
+                    t.replay.advance(m.partition(), m.offset());
+                    if let Err(e) = t.apply(key, payload) {
+                        warn!("Can't decode __consumer_offsets record: {}", e);
                     }
-
-                    // self.offset_consumer.commit_message(&m, CommitMode::Async).unwrap();
                 }
             };
         }
     }
+
+    /// Assign the partitions from their beginning. They are assigned rather than subscribed to,
+    /// so that no group is joined.
+    fn assign(&self, partitions: &[i32]) -> Result<(), String> {
+        let mut assignment = TopicPartitionList::with_capacity(partitions.len());
+        for &partition in partitions {
+            assignment.add_partition_offset(OFFSETS_TOPIC, partition, Offset::Beginning);
+        }
+        self.consumer.assign(&assignment).map_err(|e| e.to_string())
+    }
+
+    /// Record the end offset of each partition at startup, which the replay has to reach
+    /// before the committed offsets are complete. Returns the ids of the partitions, or an
+    /// error when the topic can't be read, in which case nothing is recorded.
+    fn start_replay(&self) -> Result<Vec<i32>, String> {
+        let timeout = Duration::from_secs(5);
+        let metadata = self
+            .consumer
+            .fetch_metadata(Some(OFFSETS_TOPIC), timeout)
+            .map_err(|e| e.to_string())?;
+        let mut partitions = vec![];
+        for topic in metadata.topics() {
+            if let Some(e) = topic.error() {
+                return Err(format!("{:?}", e));
+            }
+            for partition in topic.partitions() {
+                let (low, high) = self
+                    .consumer
+                    .fetch_watermarks(OFFSETS_TOPIC, partition.id(), timeout)
+                    .map_err(|e| format!("partition {}: {}", partition.id(), e))?;
+                partitions.push((partition.id(), low, high));
+            }
+        }
+        // Unauthorized or missing, the topic would otherwise leave the replay pending forever.
+        if partitions.is_empty() {
+            return Err(String::from("no partitions"));
+        }
+        self.offsets.lock().unwrap().replay.start(&partitions);
+        Ok(partitions.iter().map(|(id, _, _)| *id).collect())
+    }
 }
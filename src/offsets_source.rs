@@ -0,0 +1,156 @@
+use crate::config::{Config, OffsetsSourceKind};
+use crate::kafka;
+use crate::model::{OffsetAndMetadata, OffsetValue, OffsetsStore};
+use crate::offsets_consumer::OffsetsConsumer;
+use futures::future::BoxFuture;
+use log::{trace, warn};
+use rdkafka::consumer::{BaseConsumer, Consumer};
+use rdkafka::error::KafkaResult;
+use rdkafka::ClientConfig;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::task;
+
+/// A source of committed offsets, which keeps the shared `OffsetsStore` up to date for as long
+/// as the application runs.
+pub trait OffsetsSource: Send {
+    fn run(self: Box<Self>) -> BoxFuture<'static, ()>;
+}
+
+impl OffsetsSource for OffsetsConsumer {
+    fn run(self: Box<Self>) -> BoxFuture<'static, ()> {
+        Box::pin(OffsetsConsumer::run(*self))
+    }
+}
+
+impl OffsetsSource for CoordinatorOffsets {
+    fn run(self: Box<Self>) -> BoxFuture<'static, ()> {
+        Box::pin(CoordinatorOffsets::run(*self))
+    }
+}
+
+/// The source of the config, whose client is created at once so that an invalid config is
+/// reported before the source runs.
+pub fn build_offsets_source(
+    config: &Config,
+    offsets: Arc<Mutex<OffsetsStore>>,
+) -> KafkaResult<Box<dyn OffsetsSource>> {
+    Ok(match config.offsets_source {
+        OffsetsSourceKind::ConsumerOffsets => Box::new(OffsetsConsumer::new(
+            &config.brokers,
+            &config.client_id,
+            offsets,
+        )?),
+        OffsetsSourceKind::Coordinator => Box::new(CoordinatorOffsets::new(
+            &config.brokers,
+            &config.client_id,
+            offsets,
+            config.refresh.shortest_interval(),
+        )?),
+    })
+}
+
+/// Poll the committed offsets of every group from its coordinator (OffsetFetch). Unlike the
+/// `__consumer_offsets` replay, the commit timestamps and the group metadata are not available.
+///
+/// The offsets of each group are fetched by a consumer created for the refresh and dropped at
+/// once, so that a single group client is alive at a time. They are polled at the shortest
+/// refresh interval of the pages, for the lag of every page to be current.
+pub struct CoordinatorOffsets {
+    config: ClientConfig,
+    consumer: BaseConsumer,
+    offsets: Arc<Mutex<OffsetsStore>>,
+    interval: Duration,
+}
+
+impl CoordinatorOffsets {
+    pub fn new(
+        brokers: &str,
+        client_id: &str,
+        offsets: Arc<Mutex<OffsetsStore>>,
+        interval: Duration,
+    ) -> KafkaResult<CoordinatorOffsets> {
+        let mut config = kafka::client_config(brokers);
+        config.set("client.id", client_id);
+        Ok(CoordinatorOffsets {
+            consumer: config.create()?,
+            config,
+            offsets,
+            interval,
+        })
+    }
+
+    pub async fn run(self) {
+        loop {
+            // The fetches block for up to their timeout, which must not stall the other tasks.
+            task::block_in_place(|| self.refresh());
+            tokio::time::delay_for(self.interval).await;
+        }
+    }
+
+    fn refresh(&self) {
+        let timeout = Duration::from_secs(10);
+        let groups = match self.consumer.fetch_group_list(None, timeout) {
+            Ok(list) => list
+                .groups()
+                .iter()
+                .filter(|g| g.protocol_type() == "consumer" || g.protocol_type().is_empty())
+                .map(|g| g.name().to_owned())
+                .collect::<Vec<String>>(),
+            Err(e) => {
+                warn!("Failed to fetch group list: {}", e);
+                return;
+            }
+        };
+        let metadata = match self.consumer.fetch_metadata(None, timeout) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                warn!("Failed to fetch metadata: {}", e);
+                return;
+            }
+        };
+        let partitions = kafka::consumed_partitions(&metadata);
+
+        let mut offsets = HashMap::new();
+        let mut failed: Vec<&String> = vec![];
+        for group in &groups {
+            trace!("fetch committed offsets of group {}", group);
+            let committed = match kafka::fetch_committed_offsets(&self.config, group, &partitions) {
+                Ok(committed) => committed,
+                Err(e) => {
+                    warn!(
+                        "Failed to fetch committed offsets of group {}: {}",
+                        group, e
+                    );
+                    failed.push(group);
+                    continue;
+                }
+            };
+            for ((topic, partition), offset) in committed {
+                let key = OffsetAndMetadata::OffsetKey {
+                    group: group.to_owned(),
+                    topic,
+                    partition,
+                };
+                let value = OffsetValue {
+                    offset,
+                    leader_epoch: None,
+                    metadata: String::new(),
+                    commit_timestamp: None,
+                    expire_timestamp: None,
+                };
+                offsets.insert(key, value);
+            }
+        }
+
+        // The groups which could not be fetched keep their previous offsets.
+        let mut store = self.offsets.lock().unwrap();
+        store.offsets.retain(|key, _| match key {
+            OffsetAndMetadata::OffsetKey { group, .. } => failed.contains(&group),
+            _ => false,
+        });
+        store.offsets.extend(offsets);
+        store.replay.finish();
+    }
+}
@@ -0,0 +1,721 @@
+use crate::model::MemberAssignment;
+use crate::utils::{read_array_len, read_bytes, read_nullable_str, read_str};
+use byteorder::{BigEndian, ReadBytesExt};
+use log::warn;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Cursor, Error, ErrorKind, Read};
+
+/// Data embedded by a member in the group protocol, decoded according to the protocol type.
+pub enum MemberProtocol {
+    Consumer {
+        subscription: Option<ConsumerSubscription>,
+        assignment: Option<ConsumerAssignment>,
+        /// Tasks of the Kafka Streams members, carried in the user data of the consumer protocol.
+        streams: Option<StreamsMember>,
+    },
+    Connect {
+        worker: Option<ConnectWorker>,
+        assignment: Option<ConnectAssignment>,
+    },
+    Unknown,
+}
+
+impl MemberProtocol {
+    pub fn decode(
+        member: &str,
+        protocol_type: &str,
+        protocol: &str,
+        subscription: &[u8],
+        assignment: &[u8],
+    ) -> MemberProtocol {
+        match protocol_type {
+            "consumer" => {
+                let subscription = decode_embedded::<ConsumerSubscription>(member, subscription);
+                let assignment = decode_embedded::<ConsumerAssignment>(member, assignment);
+                let streams = match protocol {
+                    "stream" => Some(StreamsMember {
+                        subscription: subscription
+                            .as_ref()
+                            .and_then(|s| decode_embedded(member, &s.user_data)),
+                        assignment: assignment
+                            .as_ref()
+                            .and_then(|a| decode_embedded(member, &a.user_data)),
+                    }),
+                    _ => None,
+                };
+                MemberProtocol::Consumer {
+                    subscription,
+                    assignment,
+                    streams,
+                }
+            }
+            "connect" => MemberProtocol::Connect {
+                worker: decode_embedded(member, subscription),
+                assignment: decode_embedded(member, assignment),
+            },
+            _ => MemberProtocol::Unknown,
+        }
+    }
+
+    /// Partitions assigned to a consumer, none for the other protocols.
+    pub fn assignments(&self) -> &[MemberAssignment] {
+        match self {
+            MemberProtocol::Consumer {
+                assignment: Some(assignment),
+                ..
+            } => &assignment.assignments,
+            _ => &[],
+        }
+    }
+}
+
+/// Decode a subscription or an assignment of a member, None when empty or malformed.
+fn decode_embedded<'a, T>(member: &str, bytes: &'a [u8]) -> Option<T>
+where
+    T: TryFrom<&'a [u8], Error = Error>,
+{
+    if bytes.is_empty() {
+        return None;
+    }
+    T::try_from(bytes)
+        .map_err(|e| warn!("Can't decode the protocol of member {}: {}", member, e))
+        .ok()
+}
+
+/// Assignment of a member of a "consumer" group, as sent by the group leader.
+///
+/// The schema did not change from version 0 to 3, so the version is not kept. Newer versions
+/// only append fields, which are ignored.
+pub struct ConsumerAssignment {
+    pub assignments: Vec<MemberAssignment>,
+    /// Opaque data of the assignor, a null array is read as an empty one.
+    pub user_data: Vec<u8>,
+}
+
+impl TryFrom<&[u8]> for ConsumerAssignment {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rdr = Cursor::new(bytes);
+        let _version = rdr.read_i16::<BigEndian>()?;
+        let assignments = read_topic_partitions(&mut rdr)?;
+        let user_data = read_bytes(&mut rdr)?.to_vec();
+        Ok(ConsumerAssignment {
+            assignments,
+            user_data,
+        })
+    }
+}
+
+/// Subscription of a member of a "consumer" group, as sent to the group leader when joining.
+pub struct ConsumerSubscription {
+    pub version: i16,
+    pub topics: Vec<String>,
+    pub user_data: Vec<u8>,
+    /// Partitions kept by the member during a cooperative rebalance, since version 1.
+    pub owned_partitions: Vec<MemberAssignment>,
+    /// Generation of the owned partitions, since version 2.
+    pub generation: Option<i32>,
+    /// Since version 3.
+    pub rack_id: Option<String>,
+}
+
+impl TryFrom<&[u8]> for ConsumerSubscription {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rdr = Cursor::new(bytes);
+        let version = rdr.read_i16::<BigEndian>()?;
+        let topics_len = read_array_len(&mut rdr, 2)?;
+        let mut topics = Vec::with_capacity(topics_len);
+        for _ in 0..topics_len {
+            topics.push(read_str(&mut rdr)?.to_owned());
+        }
+        let user_data = read_bytes(&mut rdr)?.to_vec();
+        let owned_partitions = match version {
+            v if v >= 1 => read_topic_partitions(&mut rdr)?,
+            _ => vec![],
+        };
+        let generation = match version {
+            v if v >= 2 => Some(rdr.read_i32::<BigEndian>()?),
+            _ => None,
+        };
+        let rack_id = match version {
+            v if v >= 3 => read_nullable_str(&mut rdr)?.map(str::to_owned),
+            _ => None,
+        };
+        Ok(ConsumerSubscription {
+            version,
+            topics,
+            user_data,
+            owned_partitions,
+            generation,
+            rack_id,
+        })
+    }
+}
+
+/// Connectors and tasks of a Kafka Connect worker, as sent by the leader of the workers group.
+///
+/// Version 0 is the eager protocol, versions 1 and 2 the incremental cooperative one, which adds
+/// the revoked connectors and tasks and the delay before the next rebalance.
+pub struct ConnectAssignment {
+    pub error: i16,
+    pub leader: String,
+    pub leader_url: String,
+    pub config_offset: i64,
+    pub assigned: Vec<ConnectorTasks>,
+    pub revoked: Vec<ConnectorTasks>,
+    pub delay: Option<i32>,
+}
+
+pub struct ConnectorTasks {
+    pub connector: String,
+    /// Task ids, the connector instance itself being the task -1.
+    pub tasks: Vec<i32>,
+}
+
+impl ConnectorTasks {
+    /// "name[connector,0,1]", or "name[0,1]" when only tasks run on the worker.
+    pub fn describe(&self) -> String {
+        let tasks: Vec<String> = self
+            .tasks
+            .iter()
+            .map(|&t| match t {
+                -1 => "connector".to_owned(),
+                t => t.to_string(),
+            })
+            .collect();
+        format!("{}[{}]", self.connector, tasks.join(","))
+    }
+}
+
+impl TryFrom<&[u8]> for ConnectAssignment {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rdr = Cursor::new(bytes);
+        let version = rdr.read_i16::<BigEndian>()?;
+        let error = rdr.read_i16::<BigEndian>()?;
+        let leader = read_str(&mut rdr)?.to_owned();
+        let leader_url = read_str(&mut rdr)?.to_owned();
+        let config_offset = rdr.read_i64::<BigEndian>()?;
+        let assigned = read_connector_tasks(&mut rdr)?;
+        let (revoked, delay) = match version {
+            v if v >= 1 => (
+                read_connector_tasks(&mut rdr)?,
+                Some(rdr.read_i32::<BigEndian>()?),
+            ),
+            _ => (vec![], None),
+        };
+        Ok(ConnectAssignment {
+            error,
+            leader,
+            leader_url,
+            config_offset,
+            assigned,
+            revoked,
+            delay,
+        })
+    }
+}
+
+fn read_connector_tasks(rdr: &mut Cursor<&[u8]>) -> Result<Vec<ConnectorTasks>, Error> {
+    let connectors_len = read_array_len(rdr, 6)?;
+    let mut connectors = Vec::with_capacity(connectors_len);
+    for _ in 0..connectors_len {
+        let connector = read_str(rdr)?.to_owned();
+        let tasks_len = read_array_len(rdr, 4)?;
+        let mut tasks = Vec::with_capacity(tasks_len);
+        for _ in 0..tasks_len {
+            tasks.push(rdr.read_i32::<BigEndian>()?);
+        }
+        connectors.push(ConnectorTasks { connector, tasks });
+    }
+    Ok(connectors)
+}
+
+/// State of a Kafka Connect worker, sent when joining the workers group. The previous
+/// assignment sent by the cooperative protocol is not decoded.
+pub struct ConnectWorker {
+    pub url: String,
+    pub config_offset: i64,
+}
+
+impl TryFrom<&[u8]> for ConnectWorker {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rdr = Cursor::new(bytes);
+        let _version = rdr.read_i16::<BigEndian>()?;
+        let url = read_str(&mut rdr)?.to_owned();
+        let config_offset = rdr.read_i64::<BigEndian>()?;
+        Ok(ConnectWorker { url, config_offset })
+    }
+}
+
+/// Last version of the Kafka Streams user data with the tasks encoded as two integers.
+const STREAMS_MAX_VERSION: i32 = 9;
+
+/// Offset sum of the active tasks in the subscriptions since version 7, the standby tasks
+/// reporting their actual offsets.
+const STREAMS_LATEST_OFFSET: i64 = -2;
+
+/// A task of a Kafka Streams application, displayed as "<topic group>_<partition>".
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TaskId {
+    pub topic_group: i32,
+    pub partition: i32,
+}
+
+impl fmt::Display for TaskId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}_{}", self.topic_group, self.partition)
+    }
+}
+
+pub struct StreamsMember {
+    pub subscription: Option<StreamsSubscription>,
+    pub assignment: Option<StreamsAssignment>,
+}
+
+/// User data of the subscription of a Kafka Streams member: the tasks it owned before the
+/// rebalance, versions 1 to 9.
+pub struct StreamsSubscription {
+    pub version: i32,
+    pub process_id: String,
+    pub active_tasks: Vec<TaskId>,
+    pub standby_tasks: Vec<TaskId>,
+    /// Host and port of the interactive queries, since version 2.
+    pub endpoint: Option<String>,
+}
+
+impl TryFrom<&[u8]> for StreamsSubscription {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rdr = Cursor::new(bytes);
+        let version = read_streams_version(&mut rdr)?;
+        let mut process_id = [0u8; 16];
+        rdr.read_exact(&mut process_id)?;
+        let (mut active_tasks, mut standby_tasks) = match version {
+            v if v <= 6 => (read_task_ids(&mut rdr)?, read_task_ids(&mut rdr)?),
+            _ => (vec![], vec![]),
+        };
+        let endpoint = match version {
+            v if v >= 2 => Some(read_bytes(&mut rdr)?)
+                .filter(|e| !e.is_empty())
+                .map(|e| String::from_utf8_lossy(e).into_owned()),
+            _ => None,
+        };
+        if version >= 7 {
+            let topic_groups_len = read_array_len(&mut rdr, 8)?;
+            for _ in 0..topic_groups_len {
+                let topic_group = rdr.read_i32::<BigEndian>()?;
+                let partitions_len = read_array_len(&mut rdr, 12)?;
+                for _ in 0..partitions_len {
+                    let partition = rdr.read_i32::<BigEndian>()?;
+                    let offset_sum = rdr.read_i64::<BigEndian>()?;
+                    let task = TaskId {
+                        topic_group,
+                        partition,
+                    };
+                    match offset_sum {
+                        STREAMS_LATEST_OFFSET => active_tasks.push(task),
+                        _ => standby_tasks.push(task),
+                    }
+                }
+            }
+        }
+        Ok(StreamsSubscription {
+            version,
+            process_id: format_uuid(&process_id),
+            active_tasks,
+            standby_tasks,
+            endpoint,
+        })
+    }
+}
+
+/// User data of the assignment of a Kafka Streams member, versions 1 to 9. The partitions of
+/// the other hosts, which follow the tasks, are not decoded.
+pub struct StreamsAssignment {
+    pub version: i32,
+    pub active_tasks: Vec<TaskId>,
+    pub standby_tasks: Vec<TaskId>,
+}
+
+impl TryFrom<&[u8]> for StreamsAssignment {
+    type Error = Error;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        let mut rdr = Cursor::new(bytes);
+        let version = read_streams_version(&mut rdr)?;
+        let active_tasks = read_task_ids(&mut rdr)?;
+        // Each standby task comes with the partitions of its changelogs.
+        let standby_len = read_array_len(&mut rdr, 12)?;
+        let mut standby_tasks = Vec::with_capacity(standby_len);
+        for _ in 0..standby_len {
+            standby_tasks.push(read_task_id(&mut rdr)?);
+            let partitions_len = read_array_len(&mut rdr, 6)?;
+            for _ in 0..partitions_len {
+                read_str(&mut rdr)?;
+                rdr.read_i32::<BigEndian>()?;
+            }
+        }
+        Ok(StreamsAssignment {
+            version,
+            active_tasks,
+            standby_tasks,
+        })
+    }
+}
+
+/// The version, followed since version 3 by the latest version supported by the sender.
+fn read_streams_version(rdr: &mut Cursor<&[u8]>) -> Result<i32, Error> {
+    let version = rdr.read_i32::<BigEndian>()?;
+    if !(1..=STREAMS_MAX_VERSION).contains(&version) {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("unsupported Kafka Streams version {}", version),
+        ));
+    }
+    if version >= 3 {
+        let _latest_supported_version = rdr.read_i32::<BigEndian>()?;
+    }
+    Ok(version)
+}
+
+fn read_task_id(rdr: &mut Cursor<&[u8]>) -> Result<TaskId, Error> {
+    Ok(TaskId {
+        topic_group: rdr.read_i32::<BigEndian>()?,
+        partition: rdr.read_i32::<BigEndian>()?,
+    })
+}
+
+fn read_task_ids(rdr: &mut Cursor<&[u8]>) -> Result<Vec<TaskId>, Error> {
+    let len = read_array_len(rdr, 8)?;
+    let mut tasks = Vec::with_capacity(len);
+    for _ in 0..len {
+        tasks.push(read_task_id(rdr)?);
+    }
+    Ok(tasks)
+}
+
+fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+/// An array of topics, each with an array of partitions.
+fn read_topic_partitions(rdr: &mut Cursor<&[u8]>) -> Result<Vec<MemberAssignment>, Error> {
+    // A topic takes at least its name length and its partitions count.
+    let topics_len = read_array_len(rdr, 6)?;
+    let mut topics = Vec::with_capacity(topics_len);
+    for _ in 0..topics_len {
+        let topic = read_str(rdr)?.to_owned();
+        let partitions_len = read_array_len(rdr, 4)?;
+        let mut partitions = Vec::with_capacity(partitions_len);
+        for _ in 0..partitions_len {
+            partitions.push(rdr.read_i32::<BigEndian>()?);
+        }
+        topics.push(MemberAssignment { topic, partitions });
+    }
+    Ok(topics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn put_str(buf: &mut Vec<u8>, s: &str) {
+        buf.extend_from_slice(&(s.len() as i16).to_be_bytes());
+        buf.extend_from_slice(s.as_bytes());
+    }
+
+    fn put_topic_partitions(buf: &mut Vec<u8>, topics: &[(&str, &[i32])]) {
+        buf.extend_from_slice(&(topics.len() as i32).to_be_bytes());
+        for (topic, partitions) in topics {
+            put_str(buf, topic);
+            buf.extend_from_slice(&(partitions.len() as i32).to_be_bytes());
+            for p in partitions.iter() {
+                buf.extend_from_slice(&p.to_be_bytes());
+            }
+        }
+    }
+
+    fn assignment() -> Vec<u8> {
+        let mut buf = 1i16.to_be_bytes().to_vec();
+        put_topic_partitions(&mut buf, &[("orders", &[0, 2]), ("payments", &[1])]);
+        buf.extend_from_slice(&3i32.to_be_bytes());
+        buf.extend_from_slice(b"abc");
+        buf
+    }
+
+    fn subscription(version: i16) -> Vec<u8> {
+        let mut buf = version.to_be_bytes().to_vec();
+        buf.extend_from_slice(&2i32.to_be_bytes());
+        put_str(&mut buf, "orders");
+        put_str(&mut buf, "payments");
+        buf.extend_from_slice(&(-1i32).to_be_bytes());
+        if version >= 1 {
+            put_topic_partitions(&mut buf, &[("orders", &[0])]);
+        }
+        if version >= 2 {
+            buf.extend_from_slice(&7i32.to_be_bytes());
+        }
+        if version >= 3 {
+            put_str(&mut buf, "eu-west-1a");
+        }
+        buf
+    }
+
+    fn connect_assignment(version: i16) -> Vec<u8> {
+        let mut buf = version.to_be_bytes().to_vec();
+        buf.extend_from_slice(&0i16.to_be_bytes());
+        put_str(&mut buf, "worker-1");
+        put_str(&mut buf, "http://worker-1:8083/");
+        buf.extend_from_slice(&42i64.to_be_bytes());
+        put_topic_partitions(&mut buf, &[("jdbc-source", &[-1, 0, 1])]);
+        if version >= 1 {
+            put_topic_partitions(&mut buf, &[("s3-sink", &[2])]);
+            buf.extend_from_slice(&300i32.to_be_bytes());
+        }
+        buf
+    }
+
+    fn connect_worker() -> Vec<u8> {
+        let mut buf = 1i16.to_be_bytes().to_vec();
+        put_str(&mut buf, "http://worker-2:8083/");
+        buf.extend_from_slice(&42i64.to_be_bytes());
+        buf.extend_from_slice(&(-1i32).to_be_bytes());
+        buf
+    }
+
+    fn put_task_ids(buf: &mut Vec<u8>, tasks: &[(i32, i32)]) {
+        buf.extend_from_slice(&(tasks.len() as i32).to_be_bytes());
+        for (topic_group, partition) in tasks {
+            buf.extend_from_slice(&topic_group.to_be_bytes());
+            buf.extend_from_slice(&partition.to_be_bytes());
+        }
+    }
+
+    fn streams_subscription(version: i32) -> Vec<u8> {
+        let mut buf = version.to_be_bytes().to_vec();
+        if version >= 3 {
+            buf.extend_from_slice(&STREAMS_MAX_VERSION.to_be_bytes());
+        }
+        buf.extend_from_slice(&[0xab; 16]);
+        if version <= 6 {
+            put_task_ids(&mut buf, &[(0, 0), (1, 0)]);
+            put_task_ids(&mut buf, &[(0, 1)]);
+        }
+        if version >= 2 {
+            buf.extend_from_slice(&14i32.to_be_bytes());
+            buf.extend_from_slice(b"streams-1:7070");
+        }
+        if version >= 7 {
+            buf.extend_from_slice(&1i32.to_be_bytes());
+            buf.extend_from_slice(&0i32.to_be_bytes());
+            buf.extend_from_slice(&2i32.to_be_bytes());
+            for (partition, offset_sum) in &[(0i32, STREAMS_LATEST_OFFSET), (1, 1234)] {
+                buf.extend_from_slice(&partition.to_be_bytes());
+                buf.extend_from_slice(&offset_sum.to_be_bytes());
+            }
+        }
+        buf
+    }
+
+    fn streams_assignment() -> Vec<u8> {
+        let mut buf = 4i32.to_be_bytes().to_vec();
+        buf.extend_from_slice(&STREAMS_MAX_VERSION.to_be_bytes());
+        put_task_ids(&mut buf, &[(0, 0), (0, 2)]);
+        buf.extend_from_slice(&1i32.to_be_bytes());
+        buf.extend_from_slice(&0i32.to_be_bytes());
+        buf.extend_from_slice(&1i32.to_be_bytes());
+        put_topic_partitions(&mut buf, &[("app-store-changelog", &[1])]);
+        buf
+    }
+
+    fn decode_all(bytes: &[u8]) {
+        let _ = ConsumerAssignment::try_from(bytes);
+        let _ = ConsumerSubscription::try_from(bytes);
+        let _ = ConnectAssignment::try_from(bytes);
+        let _ = ConnectWorker::try_from(bytes);
+        let _ = StreamsSubscription::try_from(bytes);
+        let _ = StreamsAssignment::try_from(bytes);
+    }
+
+    #[test]
+    fn decodes_assignment_with_several_topics() {
+        let decoded = ConsumerAssignment::try_from(assignment().as_slice()).unwrap();
+        assert_eq!(decoded.assignments.len(), 2);
+        assert_eq!(decoded.assignments[0].topic, "orders");
+        assert_eq!(decoded.assignments[0].partitions, vec![0, 2]);
+        assert_eq!(decoded.assignments[1].topic, "payments");
+        assert_eq!(decoded.assignments[1].partitions, vec![1]);
+        assert_eq!(decoded.user_data, b"abc");
+    }
+
+    #[test]
+    fn decodes_every_subscription_version() {
+        let v0 = ConsumerSubscription::try_from(subscription(0).as_slice()).unwrap();
+        assert_eq!(v0.topics, vec!["orders", "payments"]);
+        assert!(v0.user_data.is_empty());
+        assert!(v0.owned_partitions.is_empty());
+        assert_eq!(v0.generation, None);
+
+        let v1 = ConsumerSubscription::try_from(subscription(1).as_slice()).unwrap();
+        assert_eq!(v1.owned_partitions[0].partitions, vec![0]);
+        assert_eq!(v1.generation, None);
+
+        let v3 = ConsumerSubscription::try_from(subscription(3).as_slice()).unwrap();
+        assert_eq!(v3.generation, Some(7));
+        assert_eq!(v3.rack_id.as_deref(), Some("eu-west-1a"));
+    }
+
+    #[test]
+    fn decodes_connect_assignments() {
+        let eager = ConnectAssignment::try_from(connect_assignment(0).as_slice()).unwrap();
+        assert_eq!(eager.leader, "worker-1");
+        assert_eq!(eager.config_offset, 42);
+        assert_eq!(eager.assigned[0].describe(), "jdbc-source[connector,0,1]");
+        assert!(eager.revoked.is_empty());
+        assert_eq!(eager.delay, None);
+
+        let cooperative = ConnectAssignment::try_from(connect_assignment(1).as_slice()).unwrap();
+        assert_eq!(cooperative.revoked[0].describe(), "s3-sink[2]");
+        assert_eq!(cooperative.delay, Some(300));
+
+        let worker = ConnectWorker::try_from(connect_worker().as_slice()).unwrap();
+        assert_eq!(worker.url, "http://worker-2:8083/");
+    }
+
+    #[test]
+    fn decodes_streams_user_data() {
+        let v2 = StreamsSubscription::try_from(streams_subscription(2).as_slice()).unwrap();
+        assert_eq!(v2.process_id, "abababab-abab-abab-abab-abababababab");
+        assert_eq!(v2.active_tasks.len(), 2);
+        assert_eq!(v2.standby_tasks[0].to_string(), "0_1");
+        assert_eq!(v2.endpoint.as_deref(), Some("streams-1:7070"));
+
+        let v7 = StreamsSubscription::try_from(streams_subscription(7).as_slice()).unwrap();
+        assert_eq!(v7.active_tasks[0].to_string(), "0_0");
+        assert_eq!(v7.standby_tasks[0].to_string(), "0_1");
+
+        let assignment = StreamsAssignment::try_from(streams_assignment().as_slice()).unwrap();
+        assert_eq!(assignment.active_tasks[1].to_string(), "0_2");
+        assert_eq!(assignment.standby_tasks[0].to_string(), "0_1");
+
+        let mut unsupported = streams_assignment();
+        unsupported[..4].copy_from_slice(&10i32.to_be_bytes());
+        assert!(StreamsAssignment::try_from(unsupported.as_slice()).is_err());
+    }
+
+    #[test]
+    fn decodes_member_protocol_by_type() {
+        let mut assignment = 0i16.to_be_bytes().to_vec();
+        put_topic_partitions(&mut assignment, &[("orders", &[0])]);
+        let user_data = streams_assignment();
+        assignment.extend_from_slice(&(user_data.len() as i32).to_be_bytes());
+        assignment.extend_from_slice(&user_data);
+
+        match MemberProtocol::decode("m", "consumer", "stream", &[], &assignment) {
+            MemberProtocol::Consumer {
+                streams: Some(streams),
+                ..
+            } => assert_eq!(streams.assignment.unwrap().active_tasks.len(), 2),
+            _ => panic!("expected a Kafka Streams member"),
+        }
+        let protocol = MemberProtocol::decode("m", "connect", "sessioned", &[], &[]);
+        assert!(matches!(protocol, MemberProtocol::Connect { .. }));
+        assert!(protocol.assignments().is_empty());
+    }
+
+    #[test]
+    fn rejects_truncated_payloads() {
+        let bytes = assignment();
+        for len in 0..bytes.len() {
+            assert!(ConsumerAssignment::try_from(&bytes[..len]).is_err());
+        }
+        let bytes = subscription(3);
+        for len in 0..bytes.len() {
+            assert!(ConsumerSubscription::try_from(&bytes[..len]).is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_lengths_beyond_the_payload() {
+        let mut bytes = 0i16.to_be_bytes().to_vec();
+        bytes.extend_from_slice(&i32::MAX.to_be_bytes());
+        assert!(ConsumerAssignment::try_from(bytes.as_slice()).is_err());
+        assert!(ConsumerSubscription::try_from(bytes.as_slice()).is_err());
+    }
+
+    /// xorshift, to generate reproducible inputs without a fuzzing dependency.
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn below(&mut self, n: usize) -> usize {
+            (self.next() % n as u64) as usize
+        }
+    }
+
+    #[test]
+    fn fuzz_random_bytes_never_panic() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..20_000 {
+            let len = rng.below(64);
+            let bytes: Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            decode_all(&bytes);
+        }
+    }
+
+    #[test]
+    fn fuzz_mutated_payloads_never_panic() {
+        let mut rng = Rng(0x9e37_79b9_7f4a_7c15);
+        let seeds = [
+            assignment(),
+            subscription(0),
+            subscription(1),
+            subscription(3),
+            connect_assignment(1),
+            connect_worker(),
+            streams_subscription(2),
+            streams_subscription(8),
+            streams_assignment(),
+        ];
+        for _ in 0..20_000 {
+            let mut bytes = seeds[rng.below(seeds.len())].clone();
+            for _ in 0..=rng.below(4) {
+                match rng.below(3) {
+                    0 => {
+                        let i = rng.below(bytes.len());
+                        bytes[i] = rng.next() as u8;
+                    }
+                    1 => bytes.truncate(rng.below(bytes.len() + 1)),
+                    _ => bytes.push(rng.next() as u8),
+                }
+                if bytes.is_empty() {
+                    break;
+                }
+            }
+            decode_all(&bytes);
+        }
+    }
+}
@@ -0,0 +1,297 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::time::{Duration, Instant};
+
+/// Number of samples kept per counter. One is taken on each refresh of the page which feeds the
+/// counter, so the time covered depends on the refresh intervals of the pages.
+const HISTORY_LEN: usize = 60;
+
+/// Recent samples of a value over time: a monotonic counter (a high watermark, a committed
+/// offset...) from which a rate of change is derived, or a gauge like the lag.
+#[derive(Default)]
+pub struct History {
+    samples: VecDeque<(Instant, i64)>,
+}
+
+impl History {
+    /// Record a new value. Samples closer than a second to the previous one are
+    /// ignored, as they would produce meaningless rates.
+    pub fn push(&mut self, at: Instant, value: i64) {
+        if let Some((last, _)) = self.samples.back() {
+            if at.duration_since(*last).as_secs_f64() < 1.0 {
+                return;
+            }
+        }
+        if self.samples.len() == HISTORY_LEN {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((at, value));
+    }
+
+    /// Rate per second between the two most recent samples.
+    pub fn rate(&self) -> f64 {
+        let len = self.samples.len();
+        if len < 2 {
+            return 0.0;
+        }
+        rate_between(self.samples[len - 2], self.samples[len - 1])
+    }
+
+    /// Rates per second between each consecutive samples, oldest first.
+    pub fn rates(&self) -> Vec<u64> {
+        self.samples
+            .iter()
+            .zip(self.samples.iter().skip(1))
+            .map(|(&a, &b)| rate_between(a, b).round() as u64)
+            .collect()
+    }
+
+    pub fn last(&self) -> Option<i64> {
+        self.samples.back().map(|(_, v)| *v)
+    }
+
+    /// Points as (seconds relative to `now`, value), for charts. Older samples are negative.
+    pub fn points(&self, now: Instant) -> Vec<(f64, f64)> {
+        self.samples
+            .iter()
+            .map(|(at, v)| (-now.duration_since(*at).as_secs_f64(), *v as f64))
+            .collect()
+    }
+
+    /// True when the value never decreased over the whole history and has grown, with enough
+    /// samples not to flag a single bump.
+    pub fn is_increasing(&self) -> bool {
+        self.samples.len() >= 3
+            && self
+                .samples
+                .iter()
+                .zip(self.samples.iter().skip(1))
+                .all(|((_, a), (_, b))| b >= a)
+            && self.samples.back().map(|s| s.1) > self.samples.front().map(|s| s.1)
+    }
+}
+
+/// A counter going backward (topic recreated, offsets reset) is reported as a zero rate.
+fn rate_between((t1, v1): (Instant, i64), (t2, v2): (Instant, i64)) -> f64 {
+    let elapsed = t2.duration_since(t1).as_secs_f64();
+    if elapsed <= 0.0 {
+        return 0.0;
+    }
+    ((v2 - v1) as f64 / elapsed).max(0.0)
+}
+
+/// Production and consumption rates, sampled on every refresh.
+#[derive(Default)]
+pub struct Throughput {
+    pub topics: HashMap<String, History>,
+    pub partitions: HashMap<(String, i32), History>,
+    /// Committed offsets of a group on a topic, keyed by (group, topic).
+    pub groups: HashMap<(String, String), History>,
+}
+
+impl Throughput {
+    pub fn topic_rate(&self, topic: &str) -> f64 {
+        self.topics.get(topic).map(|h| h.rate()).unwrap_or(0.0)
+    }
+
+    pub fn partition_rate(&self, topic: &str, partition: i32) -> f64 {
+        self.partitions
+            .get(&(topic.to_owned(), partition))
+            .map(|h| h.rate())
+            .unwrap_or(0.0)
+    }
+
+    pub fn group_rate(&self, group: &str, topic: &str) -> f64 {
+        self.groups
+            .get(&(group.to_owned(), topic.to_owned()))
+            .map(|h| h.rate())
+            .unwrap_or(0.0)
+    }
+}
+
+/// Lag of the consumer groups over time, at the partition, topic and group levels.
+#[derive(Default)]
+pub struct LagTracker {
+    /// Keyed by (group, topic, partition).
+    pub partitions: HashMap<(String, String, i32), History>,
+    /// Keyed by (group, topic).
+    pub topics: HashMap<(String, String), History>,
+    /// Total lag of each group over all the topics it consumes.
+    pub groups: HashMap<String, History>,
+}
+
+impl LagTracker {
+    pub fn topic_lag(&self, group: &str, topic: &str) -> Option<&History> {
+        self.topics.get(&(group.to_owned(), topic.to_owned()))
+    }
+
+    pub fn is_growing(&self, group: &str, topic: &str) -> bool {
+        self.topic_lag(group, topic)
+            .map(|h| h.is_increasing())
+            .unwrap_or(false)
+    }
+
+    /// Forget the groups which have no committed offsets anymore, deleted or expired.
+    pub fn retain_groups(&mut self, groups: &HashSet<String>) {
+        self.partitions.retain(|(g, _, _), _| groups.contains(g));
+        self.topics.retain(|(g, _), _| groups.contains(g));
+        self.groups.retain(|g, _| groups.contains(g));
+    }
+
+    pub fn growing_groups(&self) -> Vec<String> {
+        let mut groups: Vec<String> = self
+            .groups
+            .iter()
+            .filter(|(_, h)| h.is_increasing())
+            .map(|(g, _)| g.to_owned())
+            .collect();
+        groups.sort();
+        groups
+    }
+}
+
+/// Time for a group to consume its lag, given the rates at which it consumes and at which the
+/// topic is produced. None when the group does not consume faster than the topic is produced.
+pub fn catch_up_eta(lag: i64, consumption: f64, production: f64) -> Option<Duration> {
+    if lag <= 0 {
+        return Some(Duration::from_secs(0));
+    }
+    let speed = consumption - production;
+    if speed <= 0.0 {
+        return None;
+    }
+    Some(Duration::from_secs_f64(lag as f64 / speed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(start: Instant, values: &[i64]) -> History {
+        let mut history = History::default();
+        for (i, v) in values.iter().enumerate() {
+            history.push(start + Duration::from_secs(i as u64), *v);
+        }
+        history
+    }
+
+    #[test]
+    fn ignores_the_samples_closer_than_a_second() {
+        let start = Instant::now();
+        let mut history = History::default();
+        history.push(start, 10);
+        history.push(start + Duration::from_millis(500), 20);
+        assert_eq!(history.last(), Some(10));
+        history.push(start + Duration::from_secs(1), 30);
+        assert_eq!(history.last(), Some(30));
+    }
+
+    #[test]
+    fn keeps_a_bounded_history() {
+        let start = Instant::now();
+        let values: Vec<i64> = (0..HISTORY_LEN as i64 + 10).collect();
+        let history = history(start, &values);
+        assert_eq!(history.samples.len(), HISTORY_LEN);
+        assert_eq!(history.samples.front().map(|s| s.1), Some(10));
+        assert_eq!(history.last(), Some(HISTORY_LEN as i64 + 9));
+    }
+
+    #[test]
+    fn has_no_rate_without_two_samples() {
+        let start = Instant::now();
+        assert_eq!(History::default().rate(), 0.0);
+        assert!(History::default().rates().is_empty());
+        let history = history(start, &[42]);
+        assert_eq!(history.rate(), 0.0);
+        assert!(history.rates().is_empty());
+    }
+
+    #[test]
+    fn computes_the_rates_per_second() {
+        let start = Instant::now();
+        let mut history = History::default();
+        history.push(start, 0);
+        history.push(start + Duration::from_secs(2), 10);
+        history.push(start + Duration::from_secs(4), 30);
+        assert_eq!(history.rate(), 10.0);
+        assert_eq!(history.rates(), vec![5, 10]);
+    }
+
+    #[test]
+    fn reports_a_counter_going_backward_as_a_zero_rate() {
+        let start = Instant::now();
+        let later = start + Duration::from_secs(1);
+        assert_eq!(rate_between((start, 100), (later, 50)), 0.0);
+        assert_eq!(history(start, &[100, 50]).rates(), vec![0]);
+    }
+
+    #[test]
+    fn needs_three_samples_to_be_increasing() {
+        let start = Instant::now();
+        assert!(!History::default().is_increasing());
+        assert!(!history(start, &[1]).is_increasing());
+        assert!(!history(start, &[1, 2]).is_increasing());
+        assert!(history(start, &[1, 2, 3]).is_increasing());
+    }
+
+    #[test]
+    fn is_not_increasing_when_flat_or_after_a_dip() {
+        let start = Instant::now();
+        assert!(!history(start, &[5, 5, 5, 5]).is_increasing());
+        assert!(!history(start, &[1, 3, 2, 4]).is_increasing());
+        assert!(history(start, &[1, 1, 2, 2]).is_increasing());
+    }
+
+    #[test]
+    fn has_caught_up_without_lag() {
+        assert_eq!(catch_up_eta(0, 0.0, 10.0), Some(Duration::from_secs(0)));
+        assert_eq!(catch_up_eta(-3, 0.0, 10.0), Some(Duration::from_secs(0)));
+    }
+
+    #[test]
+    fn never_catches_up_without_consuming_faster_than_produced() {
+        assert_eq!(catch_up_eta(100, 10.0, 10.0), None);
+        assert_eq!(catch_up_eta(100, 5.0, 10.0), None);
+    }
+
+    #[test]
+    fn catches_up_at_the_difference_of_the_rates() {
+        assert_eq!(catch_up_eta(100, 15.0, 10.0), Some(Duration::from_secs(20)));
+    }
+
+    fn lag_tracker(start: Instant) -> LagTracker {
+        let mut lags = LagTracker::default();
+        for (group, values) in &[("billing", [1, 2, 3]), ("audit", [3, 2, 1])] {
+            let key = ((*group).to_owned(), "orders".to_owned());
+            lags.partitions
+                .insert((key.0.clone(), key.1.clone(), 0), history(start, values));
+            lags.topics.insert(key, history(start, values));
+            lags.groups
+                .insert((*group).to_owned(), history(start, values));
+        }
+        lags
+    }
+
+    #[test]
+    fn lists_the_groups_with_a_growing_lag() {
+        let lags = lag_tracker(Instant::now());
+        assert_eq!(lags.growing_groups(), vec!["billing".to_owned()]);
+        assert!(lags.is_growing("billing", "orders"));
+        assert!(!lags.is_growing("audit", "orders"));
+        assert!(!lags.is_growing("billing", "payments"));
+    }
+
+    #[test]
+    fn forgets_the_groups_which_are_gone() {
+        let mut lags = lag_tracker(Instant::now());
+        let groups: HashSet<String> = vec!["audit".to_owned()].into_iter().collect();
+        lags.retain_groups(&groups);
+        assert_eq!(
+            lags.groups.keys().collect::<Vec<_>>(),
+            vec![&"audit".to_owned()]
+        );
+        assert!(lags.topic_lag("billing", "orders").is_none());
+        assert!(lags.topic_lag("audit", "orders").is_some());
+        assert!(lags.partitions.keys().all(|(g, _, _)| g == "audit"));
+        assert_eq!(lags.partitions.len(), 1);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::cluster::ClusterClient;
+use crate::model::{
+    ClusterInfo, Event, GroupInfo, OffsetsStore, PartitionInfo, TopicDetail, TopicInfo,
+};
+use crossterm::event::KeyCode;
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
+use log::warn;
+use rdkafka::error::KafkaResult;
+use std::collections::HashMap;
+use std::sync::{mpsc, Arc, Mutex};
+use tokio::task;
+
+/// Data of a page to fetch from the cluster.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub enum Refresh {
+    TopicList,
+    TopicDetail(String),
+    GroupList,
+}
+
+/// Data fetched from the cluster in the background, applied to the `App` when received.
+pub enum Snapshot {
+    TopicList(Box<TopicListSnapshot>),
+    TopicDetail {
+        topic: String,
+        detail: Option<TopicDetail>,
+    },
+    GroupList(Vec<GroupInfo>),
+}
+
+pub struct TopicListSnapshot {
+    pub cluster_info: ClusterInfo,
+    pub group_infos: Vec<GroupInfo>,
+    pub topic_infos: Vec<TopicInfo>,
+    pub brokers: Vec<i32>,
+    pub partitions: Vec<PartitionInfo>,
+    /// High watermarks of the partitions with committed offsets, to compute the lag.
+    pub high_watermarks: HashMap<(String, i32), i64>,
+}
+
+/// Run the requests of the UI against the cluster, so that rendering and key handling never wait
+/// for the network. The snapshots are sent back as events of the main loop.
+pub struct Refresher {
+    client: Box<dyn ClusterClient>,
+    offsets: Arc<Mutex<OffsetsStore>>,
+    requests: UnboundedReceiver<Refresh>,
+    events: mpsc::Sender<Event<KeyCode>>,
+}
+
+impl Refresher {
+    pub fn new(
+        client: Box<dyn ClusterClient>,
+        offsets: Arc<Mutex<OffsetsStore>>,
+        requests: UnboundedReceiver<Refresh>,
+        events: mpsc::Sender<Event<KeyCode>>,
+    ) -> Refresher {
+        Refresher {
+            client,
+            offsets,
+            requests,
+            events,
+        }
+    }
+
+    pub async fn run(mut self) {
+        while let Some(refresh) = self.requests.next().await {
+            // Requests queued while the previous one was running are only served once.
+            let mut pending = vec![refresh];
+            while let Ok(Some(refresh)) = self.requests.try_next() {
+                if !pending.contains(&refresh) {
+                    pending.push(refresh);
+                }
+            }
+            for refresh in pending {
+                let fetched = task::block_in_place(|| {
+                    fetch(self.client.as_ref(), &self.offsets, refresh.clone())
+                });
+                // A failure is reported to the page, and the next refresh tried as usual.
+                let event = match fetched {
+                    Ok(snapshot) => Event::Refreshed(snapshot),
+                    Err(e) => {
+                        warn!("Failed to refresh: {}", e);
+                        Event::RefreshFailed(refresh, e.to_string())
+                    }
+                };
+                if self.events.send(event).is_err() {
+                    // The application is exiting.
+                    return;
+                }
+            }
+        }
+    }
+}
+
+/// Fetch the data of a page.
+pub fn fetch(
+    client: &dyn ClusterClient,
+    offsets: &Mutex<OffsetsStore>,
+    refresh: Refresh,
+) -> KafkaResult<Snapshot> {
+    Ok(match refresh {
+        Refresh::TopicList => Snapshot::TopicList(Box::new(fetch_topic_list(client, offsets)?)),
+        // The consuming groups of the topic are counted by the `App`, which has the groups.
+        Refresh::TopicDetail(topic) => Snapshot::TopicDetail {
+            detail: client.get_topic_detail(&topic, &[])?,
+            topic,
+        },
+        Refresh::GroupList => Snapshot::GroupList(client.get_group_infos()?),
+    })
+}
+
+/// Fetch the topics, the groups and the health of the cluster, and the high watermarks of the
+/// partitions with committed offsets.
+pub fn fetch_topic_list(
+    client: &dyn ClusterClient,
+    offsets: &Mutex<OffsetsStore>,
+) -> KafkaResult<TopicListSnapshot> {
+    let group_infos = client.get_group_infos()?;
+    let mut consumed: Vec<(String, i32)> = offsets
+        .lock()
+        .unwrap()
+        .committed_offsets()
+        .into_iter()
+        .map(|(_, topic, partition, _)| (topic, partition))
+        .collect();
+    consumed.sort();
+    consumed.dedup();
+    let overview = client.get_overview(&group_infos)?;
+    Ok(TopicListSnapshot {
+        cluster_info: overview.cluster_info,
+        topic_infos: overview.topic_infos,
+        group_infos,
+        brokers: overview.brokers.iter().map(|b| b.id).collect(),
+        partitions: overview.partitions,
+        high_watermarks: client.get_high_watermarks(&consumed)?,
+    })
+}
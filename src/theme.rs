@@ -0,0 +1,155 @@
+use crate::config::{ThemeColor, ThemeConfig, ThemeName};
+use std::env;
+use tui::style::{Color, Modifier, Style};
+
+/// Styles of the UI, shared by all the draw functions.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Theme {
+    /// Borders of the blocks.
+    pub border: Style,
+    /// Titles standing out, like the one of the cluster infos.
+    pub title: Style,
+    /// Selected row and tab.
+    pub selected: Style,
+    /// Keys of the footer and of the help.
+    pub key: Style,
+    /// Values which are not final yet, like a lag computed while the offsets are loading.
+    pub pending: Style,
+    pub healthy: Style,
+    /// Problems like firing alerts or under-replicated partitions.
+    pub critical: Style,
+    /// Lines of the charts, one per series.
+    pub series: Vec<Color>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::dark()
+    }
+}
+
+impl Theme {
+    /// The theme of the config, without colors when `no_color` is set whatever the config says.
+    pub fn new(config: &ThemeConfig, no_color: bool) -> Theme {
+        if no_color {
+            return Theme::no_color();
+        }
+        let mut theme = match config.name {
+            ThemeName::Dark => Theme::dark(),
+            ThemeName::Light => Theme::light(),
+            ThemeName::NoColor => return Theme::no_color(),
+        };
+        if let Some(border) = config.border {
+            theme.border = Style::default().fg(border.into());
+        }
+        theme
+    }
+
+    pub fn dark() -> Theme {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        Theme {
+            border: Style::default(),
+            title: bold.fg(Color::Cyan),
+            selected: bold.fg(Color::Yellow),
+            key: bold.fg(Color::Yellow),
+            pending: Style::default().fg(Color::Yellow),
+            healthy: Style::default().fg(Color::Green),
+            critical: bold.fg(Color::Red),
+            series: vec![
+                Color::Cyan,
+                Color::Magenta,
+                Color::Yellow,
+                Color::Green,
+                Color::Blue,
+                Color::Red,
+            ],
+        }
+    }
+
+    /// Dark colors, readable on a light background.
+    pub fn light() -> Theme {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        Theme {
+            border: Style::default(),
+            title: bold.fg(Color::Blue),
+            selected: bold.fg(Color::Blue),
+            key: bold.fg(Color::Magenta),
+            pending: Style::default().fg(Color::Magenta),
+            healthy: Style::default().fg(Color::Green),
+            critical: bold.fg(Color::Red),
+            series: vec![
+                Color::Blue,
+                Color::Magenta,
+                Color::Red,
+                Color::Green,
+                Color::Cyan,
+                Color::Black,
+            ],
+        }
+    }
+
+    /// Modifiers only, the highest contrast of the terminal.
+    pub fn no_color() -> Theme {
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        Theme {
+            border: Style::default(),
+            title: bold,
+            selected: bold.add_modifier(Modifier::REVERSED),
+            key: bold,
+            pending: Style::default().add_modifier(Modifier::ITALIC),
+            healthy: Style::default(),
+            critical: bold.add_modifier(Modifier::UNDERLINED),
+            series: vec![Color::Reset],
+        }
+    }
+}
+
+/// True when the `NO_COLOR` environment variable is set to a non empty value, see
+/// https://no-color.org.
+pub fn no_color_requested() -> bool {
+    env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+impl From<ThemeColor> for Color {
+    fn from(color: ThemeColor) -> Self {
+        match color {
+            ThemeColor::Black => Color::Black,
+            ThemeColor::Red => Color::Red,
+            ThemeColor::Green => Color::Green,
+            ThemeColor::Yellow => Color::Yellow,
+            ThemeColor::Blue => Color::Blue,
+            ThemeColor::Magenta => Color::Magenta,
+            ThemeColor::Cyan => Color::Cyan,
+            ThemeColor::Gray => Color::Gray,
+            ThemeColor::DarkGray => Color::DarkGray,
+            ThemeColor::LightRed => Color::LightRed,
+            ThemeColor::LightGreen => Color::LightGreen,
+            ThemeColor::LightYellow => Color::LightYellow,
+            ThemeColor::LightBlue => Color::LightBlue,
+            ThemeColor::LightMagenta => Color::LightMagenta,
+            ThemeColor::LightCyan => Color::LightCyan,
+            ThemeColor::White => Color::White,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colors_the_borders_of_the_profile() {
+        let config: ThemeConfig =
+            serde_json::from_str(r#"{"name": "light", "border": "red"}"#).unwrap();
+        let theme = Theme::new(&config, false);
+        assert_eq!(theme.border, Style::default().fg(Color::Red));
+        assert_eq!(theme.title, Theme::light().title);
+    }
+
+    #[test]
+    fn drops_the_colors_when_asked() {
+        let config: ThemeConfig = serde_json::from_str(r#"{"border": "red"}"#).unwrap();
+        assert_eq!(Theme::new(&config, true), Theme::no_color());
+        assert_eq!(Theme::new(&ThemeConfig::default(), false), Theme::dark());
+    }
+}
@@ -1,11 +1,21 @@
-use crate::app::App;
-use crate::model::OffsetAndMetadata::OffsetKey;
-use crate::model::TopicDetail;
+use crate::app::Context::{GroupDetailPage, GroupListPage, TopicDetailPage, TopicListPage};
+use crate::app::{App, TableArea};
+use crate::keymap::{key_label, Action};
+use crate::model::{MemberAssignment, ReplayProgress, TopicDetail};
+use crate::protocol::{ConnectorTasks, MemberProtocol, StreamsMember, TaskId};
+use crate::rates::{catch_up_eta, History};
+use crate::theme::Theme;
+use crate::utils::{format_age, format_duration};
+use std::time::Instant;
 use tui::backend::Backend;
 use tui::layout::{Constraint, Direction, Layout, Rect};
-use tui::style::{Color, Modifier, Style};
+use tui::style::{Modifier, Style};
+use tui::symbols;
 use tui::text::{Span, Spans};
-use tui::widgets::{Block, Borders, Paragraph, Row, Table, Wrap};
+use tui::widgets::{
+    Axis, Block, Borders, Chart, Clear, Dataset, GraphType, Paragraph, Row, Sparkline, Table, Tabs,
+    Wrap,
+};
 use tui::Frame;
 
 macro_rules! span_bold {
@@ -14,30 +24,450 @@ macro_rules! span_bold {
     };
 }
 
-pub fn draw<B: Backend>(backend: &mut Frame<B>, appli: &mut App) {
+/// Firing alerts listed by the banner, the others being counted in its title.
+const BANNER_ALERTS: usize = 3;
+
+/// Draw the page of the current context, below a banner of the firing alerts if any and above a
+/// footer of its keys.
+pub fn draw<B: Backend>(backend: &mut Frame<B>, app: &mut App) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(backend.size());
+    let mut area = chunks[0];
+    draw_footer(backend, app, chunks[1]);
+    let alerts = app.alerts.firing().len();
+    if alerts > 0 {
+        let height = alerts.min(BANNER_ALERTS) as u16 + 2;
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(height), Constraint::Min(0)].as_ref())
+            .split(area);
+        draw_alerts(backend, app, chunks[0]);
+        area = chunks[1];
+    }
+    app.areas.tabs.clear();
+    match app.context {
+        TopicListPage => draw_topic_list(backend, app, area),
+        TopicDetailPage => draw_topic_detail(backend, app, area),
+        GroupListPage => draw_group_list(backend, app, area),
+        GroupDetailPage => draw_group_detail(backend, app, area),
+    }
+    if app.show_help {
+        draw_help(backend, app);
+    }
+}
+
+/// The keys of the current page, the help first since it lists the others.
+fn draw_footer<B: Backend>(backend: &mut Frame<B>, app: &App, area: Rect) {
+    let keys: Vec<(String, &str)> = if app.is_editing() {
+        vec![
+            (String::from("enter"), "apply"),
+            (String::from("esc"), "cancel"),
+        ]
+    } else {
+        let mut actions = app.keymap.actions(&app.context);
+        actions.sort_by_key(|(action, _)| *action != Action::Help);
+        actions
+            .iter()
+            .map(|(action, keys)| {
+                let keys: Vec<String> = keys.iter().map(|&key| key_label(key)).collect();
+                (keys.join("/"), action.label())
+            })
+            .collect()
+    };
+    let mut spans = vec![];
+    for (key, label) in keys {
+        spans.push(Span::styled(key, app.theme.key));
+        spans.push(Span::from(format!(" {}  ", label)));
+    }
+    backend.render_widget(Paragraph::new(Spans::from(spans)), area);
+}
+
+/// A popup listing the keys of the current page over it.
+fn draw_help<B: Backend>(backend: &mut Frame<B>, app: &App) {
+    let rows: Vec<(String, &str)> = app
+        .keymap
+        .actions(&app.context)
+        .iter()
+        .map(|(action, keys)| {
+            let keys: Vec<String> = keys.iter().map(|&key| key_label(key)).collect();
+            (keys.join(", "), action.description())
+        })
+        .collect();
+    let key_width = rows.iter().map(|(keys, _)| keys.chars().count()).max();
+    let key_width = key_width.unwrap_or(0);
+    let width = rows
+        .iter()
+        .map(|(_, description)| key_width + 2 + description.len())
+        .max()
+        .unwrap_or(0);
+    let lines: Vec<Spans> = rows
+        .into_iter()
+        .map(|(keys, description)| {
+            Spans::from(vec![
+                Span::styled(format!(" {:<1$}  ", keys, key_width), app.theme.key),
+                Span::from(description),
+            ])
+        })
+        .collect();
+    // The borders and a blank column on each side.
+    let area = centered(backend.size(), width as u16 + 4, lines.len() as u16 + 2);
+    let title = match app.context {
+        TopicListPage => "Help - topics",
+        TopicDetailPage => "Help - topic",
+        GroupListPage => "Help - groups",
+        GroupDetailPage => "Help - group",
+    };
+    let help = Paragraph::new(lines).block(
+        Block::default()
+            .title(span_bold!(title))
+            .borders(Borders::ALL)
+            .border_style(app.theme.border),
+    );
+    backend.render_widget(Clear, area);
+    backend.render_widget(help, area);
+}
+
+/// An area of the given size at the center of `area`, shrunk to fit in it.
+fn centered(area: Rect, width: u16, height: u16) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+fn draw_alerts<B: Backend>(backend: &mut Frame<B>, app: &App, area: Rect) {
+    let firing = app.alerts.firing();
+    let style = app.theme.critical;
+    let mut title = format!("Alerts ({})", firing.len());
+    if firing.len() > BANNER_ALERTS {
+        title.push_str(&format!(" - {} more", firing.len() - BANNER_ALERTS));
+    }
+    let lines: Vec<Spans> = firing
+        .iter()
+        .take(BANNER_ALERTS)
+        .map(|alert| {
+            Spans::from(vec![
+                Span::styled(format!("{} ", alert.rule), style),
+                Span::from(format!("{}: {}", alert.subject, alert.message)),
+            ])
+        })
+        .collect();
+    let block = Block::default()
+        .title(Span::styled(title, style))
+        .borders(Borders::ALL)
+        .border_style(style);
+    backend.render_widget(Paragraph::new(lines).block(block), area);
+}
+
+fn draw_topic_list<B: Backend>(backend: &mut Frame<B>, appli: &mut App, area: Rect) {
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints(
             [
+                Constraint::Length(3),
                 Constraint::Percentage(20),
-                Constraint::Percentage(70),
-                Constraint::Percentage(10),
+                Constraint::Percentage(55),
+                Constraint::Percentage(25),
             ]
             .as_ref(),
         )
-        .split(backend.size());
+        .split(area);
+
+    draw_tabs(backend, appli, chunks[0]);
+    draw_cluster_infos(backend, appli, chunks[1]);
+    draw_topic_infos(backend, appli, chunks[2]);
+    draw_cluster_health(backend, appli, chunks[3]);
+}
+
+fn draw_tabs<B: Backend>(backend: &mut Frame<B>, app: &mut App, area: Rect) {
+    let selected = match app.context {
+        TopicListPage | TopicDetailPage => 0,
+        GroupListPage | GroupDetailPage => 1,
+    };
+    let pages = [("Topics", TopicListPage), ("Groups", GroupListPage)];
+    let block = Block::default()
+        .title(refresh_status(app))
+        .borders(Borders::ALL)
+        .border_style(app.theme.border);
+    // Like `Tabs`, pad each title with a blank column on both sides and end it with a divider.
+    let inner = block.inner(area);
+    let mut x = inner.left();
+    app.areas.tabs = vec![];
+    for (title, context) in pages.iter() {
+        let width = (title.len() as u16).min(inner.right().saturating_sub(x + 1));
+        app.areas
+            .tabs
+            .push((Rect::new(x + 1, inner.top(), width, 1), *context));
+        x += width + 3;
+    }
+    let titles = pages.iter().map(|(t, _)| Spans::from(*t)).collect();
+    let tabs = Tabs::new(titles)
+        .block(block)
+        .select(selected)
+        .highlight_style(app.theme.selected);
+    backend.render_widget(tabs, area);
+}
+
+fn draw_group_list<B: Backend>(backend: &mut Frame<B>, app: &mut App, area: Rect) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+        .split(area);
+    draw_tabs(backend, app, chunks[0]);
+
+    let offsets_map = app.offsets.lock().unwrap();
+    let headers = [
+        "name",
+        "state",
+        "protocol type",
+        "protocol",
+        "members",
+        "generation",
+        "lag",
+        "last commit",
+    ];
+    let values: Vec<Vec<String>> = app
+        .group_infos
+        .iter()
+        .map(|g| {
+            let generation = offsets_map
+                .groups
+                .get(&g.name)
+                .map(|m| m.generation.to_string())
+                .unwrap_or_default();
+            let lag = app
+                .lags
+                .groups
+                .get(&g.name)
+                .and_then(|h| h.last())
+                .map(|l| l.to_string())
+                .unwrap_or_default();
+            let last_commit = offsets_map
+                .last_commit(&g.name)
+                .map(format_age)
+                .unwrap_or_default();
+            vec![
+                g.name.to_owned(),
+                g.state.to_owned(),
+                g.protocol_type.to_owned(),
+                g.protocol.to_owned(),
+                g.members.len().to_string(),
+                generation,
+                lag,
+                last_commit,
+            ]
+        })
+        .collect();
+    let provisional = provisional_lag(&offsets_map.replay);
+    drop(offsets_map);
+    let rows = values.iter().map(|group| Row::Data(group.iter()));
+
+    let block = Block::default()
+        .title(format!("Groups ({}){}", app.group_infos.len(), provisional))
+        .borders(Borders::ALL)
+        .border_style(app.theme.border);
+    let table = Table::new(headers.iter(), rows)
+        .block(block)
+        .widths(fitting_columns(
+            &[
+                Constraint::Percentage(35),
+                Constraint::Length(20),
+                Constraint::Length(14),
+                Constraint::Length(12),
+                Constraint::Length(8),
+                Constraint::Length(11),
+                Constraint::Length(10),
+                Constraint::Length(14),
+            ],
+            chunks[1],
+        ))
+        .highlight_style(app.theme.selected)
+        .highlight_symbol(">> ");
+    app.areas.group_rows = table_rows(
+        chunks[1],
+        app.areas.group_rows,
+        app.group_table_state.selected(),
+    );
+    backend.render_stateful_widget(table, chunks[1], &mut app.group_table_state);
+}
+
+fn draw_group_detail<B: Backend>(backend: &mut Frame<B>, app: &mut App, area: Rect) {
+    let selected_group = app.selected_group.as_ref().unwrap();
+    let group = app.group_infos.iter().find(|g| &g.name == selected_group);
+    let offsets_map = app.offsets.lock().unwrap();
+    let metadata = offsets_map.groups.get(selected_group);
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .margin(1)
+        .constraints([Constraint::Percentage(100)].as_ref())
+        .split(area);
+    let block = Block::default()
+        .title(format!(
+            "Group {:?} - {}",
+            selected_group,
+            refresh_status(app)
+        ))
+        .borders(Borders::ALL)
+        .border_style(app.theme.border);
 
-    draw_cluster_infos(backend, appli, chunks[0]);
-    draw_topic_infos(backend, appli, chunks[1]);
+    let mut lines = vec![Spans::from(vec![
+        span_bold!("name : "),
+        Span::from(selected_group.as_str()),
+    ])];
+    if let Some(group) = group {
+        lines.push(Spans::from(vec![
+            span_bold!("state : "),
+            Span::from(group.state.as_str()),
+        ]));
+        lines.push(Spans::from(vec![
+            span_bold!("protocol : "),
+            Span::from(format!("{} / {}", group.protocol_type, group.protocol)),
+        ]));
+    } else if let Some(metadata) = metadata {
+        lines.push(Spans::from(vec![
+            span_bold!("protocol : "),
+            Span::from(format!(
+                "{} / {}",
+                metadata.protocol_type,
+                metadata.protocol.clone().unwrap_or_default()
+            )),
+        ]));
+    }
+    if let Some(metadata) = metadata {
+        lines.push(Spans::from(vec![
+            span_bold!("generation : "),
+            Span::from(metadata.generation.to_string()),
+        ]));
+        lines.push(Spans::from(vec![
+            span_bold!("leader : "),
+            Span::from(metadata.leader.clone().unwrap_or_default()),
+        ]));
+        if let Some(timestamp) = metadata.current_state_timestamp {
+            lines.push(Spans::from(vec![
+                span_bold!("state since : "),
+                Span::from(format_age(timestamp)),
+            ]));
+        }
+    }
 
-    let block = Block::default().title("WOW").borders(Borders::ALL);
-    backend.render_widget(block, chunks[2])
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(span_bold!("members")));
+    // The coordinator view stored in __consumer_offsets is the most complete, the live
+    // description is used until it has been read.
+    match metadata.filter(|m| !m.members.is_empty()) {
+        Some(metadata) => {
+            for member in &metadata.members {
+                let mut spans = vec![
+                    Span::from(format!("  {} ", member.id)),
+                    Span::from(format!(
+                        "client={} host={}",
+                        member.client_id, member.client_host
+                    )),
+                ];
+                if let Some(instance_id) = &member.group_instance_id {
+                    spans.push(Span::from(format!(" instance={}", instance_id)));
+                }
+                spans.push(Span::from(format!(
+                    " session.timeout={}ms",
+                    member.session_timeout
+                )));
+                if let Some(rebalance_timeout) = member.rebalance_timeout {
+                    spans.push(Span::from(format!(
+                        " rebalance.timeout={}ms",
+                        rebalance_timeout
+                    )));
+                }
+                spans.extend(protocol_spans(&member.protocol));
+                lines.push(Spans::from(spans));
+            }
+        }
+        None => {
+            for member in group.map(|g| g.members.as_slice()).unwrap_or_default() {
+                let mut spans = vec![
+                    Span::from(format!("  {} ", member.id)),
+                    Span::from(format!(
+                        "client={} host={}",
+                        member.client_id, member.client_host
+                    )),
+                ];
+                spans.extend(protocol_spans(&member.protocol));
+                lines.push(Spans::from(spans));
+            }
+        }
+    }
+
+    lines.push(Spans::from(""));
+    let last_commit = offsets_map
+        .last_commit(selected_group)
+        .map(format_age)
+        .unwrap_or_else(|| String::from("never"));
+    lines.push(Spans::from(vec![
+        span_bold!("committed offsets"),
+        Span::from(format!(" (last updated {})", last_commit)),
+        Span::styled(provisional_lag(&offsets_map.replay), app.theme.pending),
+    ]));
+    for (topic, partition, value) in offsets_map.group_offsets(selected_group) {
+        let lag = app
+            .lags
+            .partitions
+            .get(&(selected_group.to_owned(), topic.to_owned(), partition))
+            .and_then(|h| h.last())
+            .map(|l| l.to_string())
+            .unwrap_or_else(|| String::from("?"));
+        let mut spans = vec![
+            Span::from(format!("  {}[{}] ", topic, partition)),
+            Span::from(format!("offset={} lag={}", value.offset, lag)),
+        ];
+        if let Some(epoch) = value.leader_epoch {
+            spans.push(Span::from(format!(" leader.epoch={}", epoch)));
+        }
+        if !value.metadata.is_empty() {
+            spans.push(Span::from(format!(" metadata={:?}", value.metadata)));
+        }
+        if let Some(timestamp) = value.commit_timestamp {
+            spans.push(Span::from(format!(" committed {}", format_age(timestamp))));
+        }
+        if let Some(expire) = value.expire_timestamp {
+            spans.push(Span::from(format!(" expires {}", format_age(expire))));
+        }
+        lines.push(Spans::from(spans));
+    }
+
+    app.areas.detail_lines = lines.len() as u16;
+    let paragraph = Paragraph::new(lines)
+        .block(block)
+        .wrap(Wrap { trim: false })
+        .scroll((app.detail_scroll, 0));
+    backend.render_widget(paragraph, chunks[0]);
 }
 
-pub fn draw_topic_detail<B: Backend>(backend: &mut Frame<B>, app: &App) {
+fn draw_topic_detail<B: Backend>(backend: &mut Frame<B>, app: &mut App, area: Rect) {
     let selected_topic = app.selected_topic.as_ref().unwrap();
-    let topic_detail: &TopicDetail = app.topic_detail.as_ref().unwrap();
+    let topic_detail: &TopicDetail = match app.topic_detail.as_ref() {
+        Some(topic_detail) => topic_detail,
+        None => {
+            let block = Block::default()
+                .title(format!(
+                    "Topic {:?} - {}",
+                    selected_topic,
+                    refresh_status(app)
+                ))
+                .borders(Borders::ALL)
+                .border_style(app.theme.border);
+            let paragraph = Paragraph::new("Loading...").block(block);
+            backend.render_widget(paragraph, area);
+            return;
+        }
+    };
     let offsets_map = app.offsets.lock().unwrap();
 
     let groups = &app.group_infos;
@@ -47,14 +477,19 @@ pub fn draw_topic_detail<B: Backend>(backend: &mut Frame<B>, app: &App) {
         .collect();
 
     let chunks = Layout::default()
-        .direction(Direction::Vertical)
+        .direction(Direction::Horizontal)
         .margin(1)
-        .constraints([Constraint::Percentage(100)].as_ref())
-        .split(backend.size());
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .split(area);
 
     let block = Block::default()
-        .title(format!("Topic {:?}", selected_topic))
-        .borders(Borders::ALL);
+        .title(format!(
+            "Topic {:?} - {}",
+            selected_topic,
+            refresh_status(app)
+        ))
+        .borders(Borders::ALL)
+        .border_style(app.theme.border);
 
     let mut topic_infos = vec![
         Spans::from("Topic infos"),
@@ -67,10 +502,29 @@ pub fn draw_topic_detail<B: Backend>(backend: &mut Frame<B>, app: &App) {
             Span::from("partitions : "),
             Span::from(topic_detail.info.nb_partitions.to_string()),
         ]),
+        Spans::from(vec![
+            Span::from("replication factor : "),
+            Span::from(topic_detail.info.replication_factor.to_string()),
+        ]),
+        Spans::from(vec![
+            Span::from("cleanup policy : "),
+            Span::from(topic_detail.info.config.cleanup_policy.to_owned()),
+        ]),
+        Spans::from(vec![
+            Span::from("under-replicated partitions : "),
+            Span::from(topic_detail.info.under_replicated.to_string()),
+        ]),
         Spans::from(vec![
             Span::from("nb de messages : "),
             Span::from(topic_detail.message_count.to_string()),
         ]),
+        Spans::from(vec![
+            Span::from("msg/s : "),
+            Span::from(format!(
+                "{:.1}",
+                app.throughput.topic_rate(&topic_detail.info.name)
+            )),
+        ]),
     ];
     topic_infos.push(Spans::from("\n"));
     for offset in &topic_detail.offsets {
@@ -85,20 +539,23 @@ pub fn draw_topic_detail<B: Backend>(backend: &mut Frame<B>, app: &App) {
             Span::from(offset.high.to_string()),
             Span::from("  leader :"),
             Span::from(offset.leader.to_string()),
+            Span::from(format!(
+                "  {:.1} msg/s",
+                app.throughput
+                    .partition_rate(&topic_detail.info.name, offset.id)
+            )),
         ]))
     }
     topic_infos.push(Spans::from("\n"));
-    for consumer in consumers {
+    for consumer in &consumers {
         let mut consumer_lag = 0;
 
         let mut details: Vec<Spans> = vec![];
         for partition in &topic_detail.offsets {
-            let key = OffsetKey {
-                group: consumer.name.to_string(),
-                topic: topic_detail.info.name.to_string(),
-                partition: partition.id,
-            };
-            let offset = offsets_map.get(&key).map(|v| v.offset).unwrap_or_else(|| 0);
+            let offset = offsets_map
+                .committed(&consumer.name, &topic_detail.info.name, partition.id)
+                .map(|v| v.offset)
+                .unwrap_or(0);
             let partition_lag = partition.high - offset;
             consumer_lag += partition_lag;
             let group_partition_detail = Spans::from(vec![Span::from(format!(
@@ -108,6 +565,20 @@ pub fn draw_topic_detail<B: Backend>(backend: &mut Frame<B>, app: &App) {
             details.push(group_partition_detail);
         }
 
+        let consumption = app
+            .throughput
+            .group_rate(&consumer.name, &topic_detail.info.name);
+        let production = app.throughput.topic_rate(&topic_detail.info.name);
+        let eta = match catch_up_eta(consumer_lag, consumption, production) {
+            Some(eta) => format_duration(eta),
+            None => String::from("never"),
+        };
+        let growing = if app.lags.is_growing(&consumer.name, &topic_detail.info.name) {
+            Span::styled(" lag growing", app.theme.critical)
+        } else {
+            Span::from("")
+        };
+
         topic_infos.push(Spans::from(vec![
             Span::from("\n"),
             Span::from("\nconsumer : "),
@@ -115,24 +586,333 @@ pub fn draw_topic_detail<B: Backend>(backend: &mut Frame<B>, app: &App) {
             Span::from(format!("(state={})", consumer.state.as_str())),
             Span::from(" lag ="),
             Span::from(consumer_lag.to_string()),
+            Span::styled(provisional_lag(&offsets_map.replay), app.theme.pending),
+            Span::from(format!(" consumption = {:.1} msg/s", consumption)),
+            Span::from(format!(" catch up in {}", eta)),
+            growing,
         ]));
         topic_infos.push(Spans::from("\noffsets: \n"));
         details.iter().for_each(|d| topic_infos.push(d.to_owned()))
     }
-    let paragraph = Paragraph::new(topic_infos).block(block);
-    backend.render_widget(paragraph, chunks[0])
+    let left_chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(65), Constraint::Percentage(35)].as_ref())
+        .split(chunks[0]);
+    app.areas.detail_lines = topic_infos.len() as u16;
+    let paragraph = Paragraph::new(topic_infos)
+        .block(block)
+        .scroll((app.detail_scroll, 0));
+    backend.render_widget(paragraph, left_chunks[0]);
+
+    let lags: Vec<(&str, Option<&History>)> = consumers
+        .iter()
+        .map(|c| {
+            (
+                c.name.as_str(),
+                app.lags.topic_lag(&c.name, &topic_detail.info.name),
+            )
+        })
+        .collect();
+    draw_lag_chart(backend, &app.theme, &lags, left_chunks[1]);
+
+    let mut histories = vec![(
+        String::from("produced msg/s"),
+        app.throughput.topics.get(&topic_detail.info.name),
+    )];
+    for consumer in &consumers {
+        histories.push((
+            format!("{} msg/s", consumer.name),
+            app.throughput
+                .groups
+                .get(&(consumer.name.to_owned(), topic_detail.info.name.to_owned())),
+        ));
+    }
+    draw_sparklines(backend, &app.theme, &histories, chunks[1]);
+}
+
+fn format_assignments(assignments: &[MemberAssignment]) -> String {
+    let assignments: Vec<String> = assignments
+        .iter()
+        .map(|a| format!("{}{:?}", a.topic, a.partitions))
+        .collect();
+    assignments.join(" ")
+}
+
+fn format_tasks(tasks: &[TaskId]) -> String {
+    let tasks: Vec<String> = tasks.iter().map(TaskId::to_string).collect();
+    tasks.join(",")
+}
+
+/// What a member subscribed to and was assigned, according to the protocol of its group.
+fn protocol_spans(protocol: &MemberProtocol) -> Vec<Span<'static>> {
+    let mut details = vec![];
+    match protocol {
+        MemberProtocol::Consumer {
+            subscription,
+            assignment,
+            streams,
+        } => {
+            if let Some(assignment) = assignment {
+                details.push(format!(
+                    "assignments={}",
+                    format_assignments(&assignment.assignments)
+                ));
+            }
+            if let Some(subscription) = subscription {
+                details.push(format!(
+                    "subscription(v{})={}",
+                    subscription.version,
+                    subscription.topics.join(",")
+                ));
+                if !subscription.owned_partitions.is_empty() {
+                    details.push(format!(
+                        "owned={}",
+                        format_assignments(&subscription.owned_partitions)
+                    ));
+                }
+                if let Some(generation) = subscription.generation {
+                    details.push(format!("owned.generation={}", generation));
+                }
+                if let Some(rack_id) = &subscription.rack_id {
+                    details.push(format!("rack={}", rack_id));
+                }
+            }
+            match streams {
+                Some(streams) => details.extend(streams_details(streams)),
+                None => {
+                    if let Some(assignment) =
+                        assignment.as_ref().filter(|a| !a.user_data.is_empty())
+                    {
+                        details.push(format!(
+                            "assignment.user_data={}B",
+                            assignment.user_data.len()
+                        ));
+                    }
+                }
+            }
+        }
+        MemberProtocol::Connect { worker, assignment } => {
+            if let Some(worker) = worker {
+                details.push(format!(
+                    "url={} worker.config.offset={}",
+                    worker.url, worker.config_offset
+                ));
+            }
+            if let Some(assignment) = assignment {
+                let describe = |connectors: &[ConnectorTasks]| -> String {
+                    let connectors: Vec<String> =
+                        connectors.iter().map(ConnectorTasks::describe).collect();
+                    connectors.join(" ")
+                };
+                details.push(format!(
+                    "leader={} ({})",
+                    assignment.leader, assignment.leader_url
+                ));
+                details.push(format!("config.offset={}", assignment.config_offset));
+                details.push(format!("connectors={}", describe(&assignment.assigned)));
+                if !assignment.revoked.is_empty() {
+                    details.push(format!("revoked={}", describe(&assignment.revoked)));
+                }
+                if let Some(delay) = assignment.delay.filter(|&d| d > 0) {
+                    details.push(format!("rebalance.delay={}ms", delay));
+                }
+                if assignment.error != 0 {
+                    details.push(format!("error={}", assignment.error));
+                }
+            }
+        }
+        MemberProtocol::Unknown => {}
+    }
+    details
+        .into_iter()
+        .map(|detail| Span::from(format!(" {}", detail)))
+        .collect()
+}
+
+/// Tasks of a Kafka Streams member: those assigned, and those it owned before the rebalance.
+fn streams_details(streams: &StreamsMember) -> Vec<String> {
+    let mut details = vec![];
+    if let Some(assignment) = &streams.assignment {
+        details.push(format!(
+            "streams(v{}) active={} standby={}",
+            assignment.version,
+            format_tasks(&assignment.active_tasks),
+            format_tasks(&assignment.standby_tasks)
+        ));
+    }
+    if let Some(subscription) = &streams.subscription {
+        details.push(format!(
+            "previous(v{}) active={} standby={}",
+            subscription.version,
+            format_tasks(&subscription.active_tasks),
+            format_tasks(&subscription.standby_tasks)
+        ));
+        details.push(format!("process={}", subscription.process_id));
+        if let Some(endpoint) = &subscription.endpoint {
+            details.push(format!("endpoint={}", endpoint));
+        }
+    }
+    details
+}
+
+/// The rows of a table drawn with borders and a header in `area`, and the index of the first
+/// one shown. `Table` keeps this offset private, it is computed the same way from the previous one
+/// so that the selected row is shown.
+fn table_rows(area: Rect, previous: TableArea, selected: Option<usize>) -> TableArea {
+    let inner = Block::default().borders(Borders::ALL).inner(area);
+    // Below the header and its gap.
+    let rows = Rect {
+        y: inner.y + 2,
+        height: inner.height.saturating_sub(2),
+        ..inner
+    };
+    let height = rows.height as usize;
+    let offset = match selected {
+        Some(_) if height == 0 => previous.offset,
+        Some(selected) if selected + 1 >= height + previous.offset => selected + 1 - height,
+        Some(selected) if selected < previous.offset => selected,
+        Some(_) => previous.offset,
+        None => 0,
+    };
+    TableArea { rows, offset }
+}
+
+/// The leading columns of a bordered table which fit in `area`, the others are not shown: tui
+/// draws past the edge of the buffer, and panics, when the columns are wider than the table.
+fn fitting_columns(widths: &[Constraint], area: Rect) -> &[Constraint] {
+    let available = area.width.saturating_sub(2);
+    let mut used = 0;
+    let count = widths
+        .iter()
+        .take_while(|constraint| {
+            let width = match **constraint {
+                Constraint::Length(v) | Constraint::Min(v) => v,
+                Constraint::Percentage(p) => (u32::from(available) * u32::from(p) / 100) as u16,
+                Constraint::Ratio(n, d) => (u32::from(available) * n / d.max(1)) as u16,
+                Constraint::Max(_) => 0,
+            };
+            // Separated by a space from the previous column.
+            used += width + if used == 0 { 0 } else { 1 };
+            used <= available
+        })
+        .count();
+    &widths[..count]
+}
+
+/// Age of the data of the page, whether its last refresh failed, and whether it is paused.
+fn refresh_status(app: &App) -> String {
+    let mut updated = match app.last_updated() {
+        Some(updated) => format!("updated {} ago", format_duration(updated.elapsed())),
+        None => String::from("loading"),
+    };
+    if let Some(error) = app.refresh_error() {
+        updated = format!("{}, refresh failed: {}", updated, error);
+    }
+    if app.paused {
+        format!("{} (paused, p to resume)", updated)
+    } else {
+        updated
+    }
+}
+
+/// Marker of the lag figures computed before the committed offsets are fully loaded.
+fn provisional_lag(replay: &ReplayProgress) -> String {
+    if replay.is_complete() {
+        String::new()
+    } else {
+        format!(" (provisional, offsets loading {}%)", replay.percentage())
+    }
+}
+
+/// Chart the lag history of each group, over the last minutes.
+fn draw_lag_chart<B: Backend>(
+    backend: &mut Frame<B>,
+    theme: &Theme,
+    lags: &[(&str, Option<&History>)],
+    area: Rect,
+) {
+    let now = Instant::now();
+    let points: Vec<(&str, Vec<(f64, f64)>)> = lags
+        .iter()
+        .map(|(group, history)| (*group, history.map(|h| h.points(now)).unwrap_or_default()))
+        .collect();
+    let oldest = points
+        .iter()
+        .flat_map(|(_, p)| p.iter().map(|(x, _)| *x))
+        .fold(0.0, f64::min);
+    let max_lag = points
+        .iter()
+        .flat_map(|(_, p)| p.iter().map(|(_, y)| *y))
+        .fold(0.0, f64::max);
+
+    let datasets = points
+        .iter()
+        .zip(theme.series.iter().cycle())
+        .map(|((group, data), color)| {
+            Dataset::default()
+                .name(*group)
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(*color))
+                .data(data)
+        })
+        .collect();
+    let chart = Chart::new(datasets)
+        .block(
+            Block::default()
+                .title("Lag")
+                .borders(Borders::ALL)
+                .border_style(theme.border),
+        )
+        .x_axis(Axis::default().bounds([oldest, 0.0]).labels(vec![
+            Span::from(format!("{:.0}s", oldest)),
+            Span::from("now"),
+        ]))
+        .y_axis(
+            Axis::default()
+                .bounds([0.0, max_lag.max(1.0)])
+                .labels(vec![Span::from("0"), Span::from(format!("{:.0}", max_lag))]),
+        );
+    backend.render_widget(chart, area);
+}
+
+/// Stack one sparkline per history, as many as the area can hold.
+fn draw_sparklines<B: Backend>(
+    backend: &mut Frame<B>,
+    theme: &Theme,
+    histories: &[(String, Option<&History>)],
+    area: Rect,
+) {
+    let height = 5;
+    let count = ((area.height / height) as usize).min(histories.len());
+    let mut constraints = vec![Constraint::Length(height); count];
+    constraints.push(Constraint::Min(0));
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(constraints)
+        .split(area);
+
+    for ((title, history), chunk) in histories.iter().zip(chunks.iter().take(count)) {
+        let data = history.map(|h| h.rates()).unwrap_or_default();
+        let sparkline = Sparkline::default()
+            .block(
+                Block::default()
+                    .title(title.as_str())
+                    .borders(Borders::ALL)
+                    .border_style(theme.border),
+            )
+            .data(&data)
+            .style(theme.healthy);
+        backend.render_widget(sparkline, *chunk);
+    }
 }
 
 fn draw_cluster_infos<B: Backend>(backend: &mut Frame<B>, app: &mut App, area: Rect) {
     let ci = &app.cluster_info;
     let cluster_block = Block::default()
-        .title(Span::styled(
-            "Cluster infos",
-            Style::default()
-                .fg(Color::Cyan)
-                .add_modifier(Modifier::BOLD),
-        ))
-        .borders(Borders::ALL);
+        .title(Span::styled("Cluster infos", app.theme.title))
+        .borders(Borders::ALL)
+        .border_style(app.theme.border);
     let cluster_infos = vec![
         Spans::from(format!("message: {}", app.message)),
         Spans::from("Here are the kafka cluster infos"),
@@ -160,31 +940,395 @@ fn draw_cluster_infos<B: Backend>(backend: &mut Frame<B>, app: &mut App, area: R
     backend.render_widget(paragraph, area);
 }
 
+fn draw_cluster_health<B: Backend>(backend: &mut Frame<B>, app: &App, area: Rect) {
+    let health = &app.cluster_health;
+    let block = Block::default()
+        .title("Cluster health")
+        .borders(Borders::ALL)
+        .border_style(app.theme.border);
+
+    let counter = |label: &'static str, count: usize| {
+        let style = if count > 0 {
+            app.theme.critical
+        } else {
+            app.theme.healthy
+        };
+        Spans::from(vec![
+            span_bold!(label),
+            Span::styled(count.to_string(), style),
+        ])
+    };
+
+    let leaders: Vec<String> = health
+        .leadership
+        .iter()
+        .map(|b| {
+            format!(
+                "{} leads {}/{}",
+                b.broker_id, b.leader_count, b.preferred_count
+            )
+        })
+        .collect();
+    let lagging = if health.growing_lag_groups.is_empty() {
+        Span::from("none")
+    } else {
+        Span::styled(health.growing_lag_groups.join(", "), app.theme.critical)
+    };
+
+    let lines = vec![
+        counter("under-replicated partitions : ", health.under_replicated),
+        counter("offline partitions : ", health.offline),
+        counter("under min ISR partitions : ", health.under_min_isr),
+        Spans::from(vec![
+            span_bold!("leaders (actual/preferred) : "),
+            Span::from(leaders.join(", ")),
+        ]),
+        Spans::from(vec![
+            span_bold!("groups with growing lag : "),
+            lagging,
+            Span::styled(
+                provisional_lag(&app.offsets.lock().unwrap().replay),
+                app.theme.pending,
+            ),
+        ]),
+    ];
+    let paragraph = Paragraph::new(lines).block(block).wrap(Wrap { trim: true });
+    backend.render_widget(paragraph, area);
+}
+
 fn draw_topic_infos<B: Backend>(backend: &mut Frame<B>, appli: &mut App, area: Rect) {
+    let view = &appli.topic_view;
+    let area = if view.editing || !view.query.is_empty() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(3), Constraint::Min(0)].as_ref())
+            .split(area);
+        let cursor = if view.editing { "_" } else { "" };
+        let search = Paragraph::new(Spans::from(vec![
+            span_bold!("/"),
+            Span::from(format!("{}{}", view.query, cursor)),
+        ]))
+        .block(
+            Block::default()
+                .title("Search")
+                .borders(Borders::ALL)
+                .border_style(appli.theme.border),
+        );
+        backend.render_widget(search, chunks[0]);
+        chunks[1]
+    } else {
+        area
+    };
+
     let topics = &appli.topic_infos;
-    let headers = ["name", "partitions nb"];
+    let headers = [
+        "",
+        "name",
+        "partitions",
+        "RF",
+        "messages",
+        "msg/s",
+        "cleanup",
+        "groups",
+    ];
 
-    let values: Vec<Vec<String>> = topics
+    let values: Vec<Vec<String>> = view
+        .visible
         .iter()
-        .map(|ti| vec![ti.name.to_owned(), ti.nb_partitions.to_string()])
+        .map(|&i| &topics[i])
+        .map(|ti| {
+            let marker = if ti.under_replicated > 0 { "!" } else { "" };
+            vec![
+                marker.to_string(),
+                ti.name.to_owned(),
+                ti.nb_partitions.to_string(),
+                ti.replication_factor.to_string(),
+                ti.message_count.to_string(),
+                format!("{:.1}", appli.throughput.topic_rate(&ti.name)),
+                ti.config.cleanup_policy.to_owned(),
+                ti.group_count.to_string(),
+            ]
+        })
         .collect();
     let rows = values.iter().map(|top| Row::Data(top.iter()));
 
-    let block = Block::default().title("Topics").borders(Borders::ALL);
-
-    let selected_style = Style::default()
-        .fg(Color::Yellow)
-        .add_modifier(Modifier::BOLD);
+    let title = format!(
+        "Topics ({}/{}) sorted by {} {}{}",
+        view.visible.len(),
+        topics.len(),
+        view.sort.label(),
+        if view.ascending { "asc" } else { "desc" },
+        if view.hide_internal {
+            ", internal hidden"
+        } else {
+            ""
+        }
+    );
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(appli.theme.border);
 
     let table = Table::new(headers.iter(), rows)
         .block(block)
-        .widths(&[
-            Constraint::Percentage(50),
-            Constraint::Length(30),
-            Constraint::Max(10),
-        ])
-        .highlight_style(selected_style)
+        .widths(fitting_columns(
+            &[
+                Constraint::Length(1),
+                Constraint::Percentage(40),
+                Constraint::Length(10),
+                Constraint::Length(3),
+                Constraint::Length(12),
+                Constraint::Length(8),
+                Constraint::Length(15),
+                Constraint::Length(6),
+            ],
+            area,
+        ))
+        .highlight_style(appli.theme.selected)
         .highlight_symbol(">> ");
 
+    appli.areas.topic_rows = table_rows(
+        area,
+        appli.areas.topic_rows,
+        appli.topic_table_state.selected(),
+    );
     backend.render_stateful_widget(table, area, &mut appli.topic_table_state);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::Alerts;
+    use crate::app::Context;
+    use crate::config::{AlertCondition, AlertRule, RefreshConfig};
+    use crate::fake_cluster::FakeCluster;
+    use crate::model::OffsetsStore;
+    use crate::rates::LagTracker;
+    use crate::refresher::{fetch, Refresh};
+    use futures::channel::mpsc::unbounded;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use tui::backend::TestBackend;
+    use tui::buffer::Buffer;
+    use tui::style::Color;
+    use tui::Terminal;
+
+    /// Terminal sizes each page is rendered at: cramped, default and large.
+    const SIZES: [(u16, u16); 3] = [(40, 12), (80, 24), (140, 40)];
+
+    const LONG_NAME: &str =
+        "com.example.accounting.invoices.generated-by-the-legacy-billing-platform.v2.dead-letters";
+
+    fn app() -> App {
+        let (requests, _) = unbounded();
+        App::new(requests, RefreshConfig::default())
+    }
+
+    fn cluster() -> FakeCluster {
+        let mut cluster = FakeCluster::sample()
+            .with_topic("__consumer_offsets", 2)
+            .with_topic(LONG_NAME, 1)
+            .with_group("audit", &[("orders", &[0, 1])]);
+        cluster.produce("orders", 0, 1200);
+        cluster.produce("orders", 1, 800);
+        cluster.produce(LONG_NAME, 0, 3);
+        cluster.partition_mut("orders", 1).isr = vec![2];
+        cluster
+    }
+
+    /// An `App` showing the fake cluster, with the offsets committed by `billing` loaded.
+    fn loaded_app() -> App {
+        let cluster = cluster();
+        let mut app = app();
+        *app.offsets.lock().unwrap() = OffsetsStore::with_committed(&[
+            ("billing", "orders", 0, 1000),
+            ("billing", "orders", 1, 785),
+        ]);
+        let snapshot = fetch(&cluster, &app.offsets, Refresh::TopicList).unwrap();
+        app.apply_snapshot(snapshot);
+        // The lag charts are placed by the age of the samples, which depends on the test speed.
+        app.lags = LagTracker::default();
+        app
+    }
+
+    fn open_topic(app: &mut App, cluster: &FakeCluster, topic: &str) {
+        app.context = Context::TopicDetailPage;
+        app.selected_topic = Some(topic.to_owned());
+        let snapshot = fetch(
+            cluster,
+            &app.offsets,
+            Refresh::TopicDetail(topic.to_owned()),
+        )
+        .unwrap();
+        app.apply_snapshot(snapshot);
+        app.lags = LagTracker::default();
+    }
+
+    /// Text of the rendered cells, without the trailing blanks of the lines.
+    fn buffer_text(buffer: &Buffer) -> String {
+        let width = buffer.area.width as usize;
+        let mut text = String::new();
+        for line in buffer.content.chunks(width) {
+            let line: String = line.iter().map(|cell| cell.symbol.as_str()).collect();
+            text.push_str(line.trim_end());
+            text.push('\n');
+        }
+        text
+    }
+
+    /// Render a page at every size and compare it to its golden files in `src/snapshots`. Set
+    /// `UPDATE_SNAPSHOTS=1` to write the golden files from the current rendering instead.
+    fn assert_snapshots<F>(name: &str, app: &mut App, draw: F)
+    where
+        F: Fn(&mut Frame<TestBackend>, &mut App),
+    {
+        let update = env::var_os("UPDATE_SNAPSHOTS").is_some();
+        for &(width, height) in SIZES.iter() {
+            let mut terminal = Terminal::new(TestBackend::new(width, height)).unwrap();
+            terminal.draw(|f| draw(f, app)).unwrap();
+            let actual = buffer_text(terminal.backend().buffer());
+
+            let path: PathBuf = [
+                env!("CARGO_MANIFEST_DIR"),
+                "src",
+                "snapshots",
+                &format!("{}_{}x{}.txt", name, width, height),
+            ]
+            .iter()
+            .collect();
+            if update {
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, &actual).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&path).unwrap_or_else(|_| {
+                panic!(
+                    "missing golden file {:?}, run with UPDATE_SNAPSHOTS=1",
+                    path
+                )
+            });
+            assert!(
+                actual == expected,
+                "{} at {}x{} differs from {:?}\n--- actual\n{}",
+                name,
+                width,
+                height,
+                path,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn topic_list_of_an_empty_cluster() {
+        assert_snapshots("topic_list_empty", &mut app(), draw);
+    }
+
+    #[test]
+    fn topic_list() {
+        assert_snapshots("topic_list", &mut loaded_app(), draw);
+    }
+
+    #[test]
+    fn topic_list_filtered() {
+        let mut app = loaded_app();
+        app.start_topic_search();
+        for c in "ordrs".chars() {
+            app.push_topic_search(c);
+        }
+        assert_snapshots("topic_list_filtered", &mut app, draw);
+    }
+
+    #[test]
+    fn cluster_infos() {
+        assert_snapshots("cluster_infos_empty", &mut app(), |f, app| {
+            draw_cluster_infos(f, app, f.size())
+        });
+        assert_snapshots("cluster_infos", &mut loaded_app(), |f, app| {
+            draw_cluster_infos(f, app, f.size())
+        });
+    }
+
+    #[test]
+    fn topic_infos() {
+        assert_snapshots("topic_infos_empty", &mut app(), |f, app| {
+            draw_topic_infos(f, app, f.size())
+        });
+        let mut app = loaded_app();
+        app.toggle_internal_topics();
+        app.reverse_topic_sort();
+        assert_snapshots("topic_infos", &mut app, |f, app| {
+            draw_topic_infos(f, app, f.size())
+        });
+    }
+
+    #[test]
+    fn topic_detail_loading() {
+        let mut app = loaded_app();
+        app.context = Context::TopicDetailPage;
+        app.selected_topic = Some(String::from("orders"));
+        assert_snapshots("topic_detail_loading", &mut app, draw);
+    }
+
+    #[test]
+    fn topic_detail() {
+        let mut app = loaded_app();
+        open_topic(&mut app, &cluster(), "orders");
+        assert_snapshots("topic_detail", &mut app, draw);
+    }
+
+    #[test]
+    fn topic_detail_without_committed_offsets() {
+        let mut app = loaded_app();
+        app.offsets.lock().unwrap().offsets.clear();
+        open_topic(&mut app, &cluster(), LONG_NAME);
+        assert_snapshots("topic_detail_long_name", &mut app, draw);
+    }
+
+    #[test]
+    fn alert_banner() {
+        let mut app = loaded_app();
+        let rule = |name: &str, condition| AlertRule {
+            name: name.to_owned(),
+            condition,
+            for_ms: 0,
+        };
+        app.alerts = Alerts::new(vec![
+            rule(
+                "replication",
+                AlertCondition::UnderReplicatedPartitions { topic: None },
+            ),
+            rule(
+                "stable-groups",
+                AlertCondition::GroupState {
+                    group: None,
+                    states: vec![String::from("Stable")],
+                },
+            ),
+        ]);
+        app.on_tick();
+        assert_snapshots("alert_banner", &mut app, draw);
+    }
+
+    #[test]
+    fn help() {
+        let mut app = loaded_app();
+        app.show_help = true;
+        assert_snapshots("help", &mut app, draw);
+    }
+
+    #[test]
+    fn renders_no_colors_with_the_no_color_theme() {
+        let mut app = loaded_app();
+        app.theme = Theme::no_color();
+        app.show_help = true;
+        let mut terminal = Terminal::new(TestBackend::new(140, 40)).unwrap();
+        terminal.draw(|f| draw(f, &mut app)).unwrap();
+        let buffer = terminal.backend().buffer();
+        assert!(buffer
+            .content
+            .iter()
+            .all(|cell| cell.fg == Color::Reset && cell.bg == Color::Reset));
+    }
+}
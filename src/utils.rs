@@ -1,12 +1,149 @@
 use byteorder::{BigEndian, ReadBytesExt};
-use std::io::Error;
 use std::io::{BufRead, Cursor};
+use std::io::{Error, ErrorKind};
 use std::str;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 pub fn read_str<'a>(rdr: &'a mut Cursor<&[u8]>) -> Result<&'a str, Error> {
-    let len = (rdr.read_i16::<BigEndian>())? as usize;
+    read_nullable_str(rdr)?
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "unexpected null string"))
+}
+
+/// Read a string whose length is -1 when null.
+pub fn read_nullable_str<'a>(rdr: &'a mut Cursor<&[u8]>) -> Result<Option<&'a str>, Error> {
+    let len = rdr.read_i16::<BigEndian>()?;
+    if len < 0 {
+        return Ok(None);
+    }
+    let slice = read_slice(rdr, len as usize)?;
+    str::from_utf8(slice)
+        .map(Some)
+        .map_err(|e| Error::new(ErrorKind::InvalidData, e))
+}
+
+/// Read a byte array prefixed by its i32 length, a null array being read as an empty one.
+pub fn read_bytes<'a>(rdr: &'a mut Cursor<&[u8]>) -> Result<&'a [u8], Error> {
+    let len = rdr.read_i32::<BigEndian>()?;
+    if len < 0 {
+        return Ok(&[]);
+    }
+    read_slice(rdr, len as usize)
+}
+
+/// Read the i32 length of an array, a null array being read as an empty one. The length is
+/// checked against the remaining bytes, given the minimal size of an element, so that a
+/// corrupted length does not allocate nor loop for nothing.
+pub fn read_array_len(rdr: &mut Cursor<&[u8]>, min_element_size: usize) -> Result<usize, Error> {
+    let len = rdr.read_i32::<BigEndian>()?;
+    if len < 0 {
+        return Ok(0);
+    }
+    let remaining = rdr.get_ref().len().saturating_sub(rdr.position() as usize);
+    if (len as usize).saturating_mul(min_element_size) > remaining {
+        return Err(Error::new(
+            ErrorKind::UnexpectedEof,
+            "array longer than the buffer",
+        ));
+    }
+    Ok(len as usize)
+}
+
+fn read_slice<'a>(rdr: &'a mut Cursor<&[u8]>, len: usize) -> Result<&'a [u8], Error> {
     let pos = rdr.position() as usize;
-    let slice = str::from_utf8(&rdr.get_ref()[pos..(pos + len)]).expect("kaboom");
+    let end = pos
+        .checked_add(len)
+        .filter(|&end| end <= rdr.get_ref().len())
+        .ok_or_else(|| Error::new(ErrorKind::UnexpectedEof, "field longer than the buffer"))?;
     rdr.consume(len);
-    Ok(slice)
+    Ok(&rdr.get_ref()[pos..end])
+}
+
+/// Case insensitive fuzzy matching: every character of the pattern must appear in the text, in
+/// the same order but not necessarily contiguous.
+pub fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut text_chars = text.chars().flat_map(char::to_lowercase);
+    pattern
+        .chars()
+        .flat_map(char::to_lowercase)
+        .all(|p| text_chars.any(|t| t == p))
+}
+
+/// Human readable duration, with the two most significant units: "2h05m", "3m12s", "42s".
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        s if s >= 3600 => format!("{}h{:02}m", s / 3600, (s % 3600) / 60),
+        s if s >= 60 => format!("{}m{:02}s", s / 60, s % 60),
+        s => format!("{}s", s),
+    }
+}
+
+/// Distance from now of a timestamp in milliseconds since the epoch: "3m12s ago", "in 2h05m".
+pub fn format_age(timestamp_ms: i64) -> String {
+    let now = now_ms();
+    let distance = Duration::from_millis((now - timestamp_ms).unsigned_abs());
+    if timestamp_ms > now {
+        format!("in {}", format_duration(distance))
+    } else {
+        format!("{} ago", format_duration(distance))
+    }
+}
+
+/// Milliseconds since the epoch, the unit of the Kafka timestamps.
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+/// Parse a duration written as a number followed by a unit among ms, s, m, h and d: "500ms",
+/// "30s", "5m".
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let split = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    let (value, unit) = text.split_at(split);
+    let value: u64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration {:?}, expected e.g. 30s or 5m", text))?;
+    let millis = match unit {
+        "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "h" => 60 * 60 * 1000,
+        "d" => 24 * 60 * 60 * 1000,
+        _ => {
+            return Err(format!(
+                "invalid duration unit in {:?}, expected ms, s, m, h or d",
+                text
+            ))
+        }
+    };
+    value
+        .checked_mul(millis)
+        .map(Duration::from_millis)
+        .ok_or_else(|| format!("invalid duration {:?}, expected e.g. 30s or 5m", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("5m"), Ok(Duration::from_secs(300)));
+        assert_eq!(parse_duration("2d"), Ok(Duration::from_secs(2 * 24 * 3600)));
+    }
+
+    #[test]
+    fn rejects_the_durations_too_long_to_represent() {
+        assert_eq!(
+            parse_duration("99999999999999999d"),
+            Err(String::from(
+                "invalid duration \"99999999999999999d\", expected e.g. 30s or 5m"
+            ))
+        );
+    }
 }